@@ -19,19 +19,25 @@ pub struct Enemy;
 pub struct Melee {
     dmg: f64,
     hitrate: f64,
+    damage_type: DamageType,
 
     cooldown: f64,
 }
 
 impl Melee {
-    pub fn new(dmg: f64, hitrate: f64) -> Self {
+    pub fn new(dmg: f64, hitrate: f64, damage_type: DamageType) -> Self {
         Melee {
             dmg,
             hitrate,
+            damage_type,
 
             cooldown: 0.0,
         }
     }
+
+    pub fn set_damage(&mut self, dmg: f64) {
+        self.dmg = dmg;
+    }
 }
 
 #[derive(SystemData)]
@@ -44,7 +50,13 @@ pub struct MeleeSystemData<'a> {
     bb: ReadStorage<'a, BoundingBox>,
     state: ReadStorage<'a, UnitState>,
     melee: WriteStorage<'a, Melee>,
+    stagger: WriteStorage<'a, Stagger>,
+    resistance: ReadStorage<'a, Resistance>,
+    effects: WriteStorage<'a, StatusEffects>,
+    veterancy: WriteStorage<'a, Veterancy>,
     health: WriteStorage<'a, Health>,
+    stats: Write<'a, Statistics>,
+    events: Write<'a, EventLog>,
     updater: Read<'a, LazyUpdate>,
 }
 
@@ -70,40 +82,77 @@ impl<'a> System<'a> for MeleeSystem {
             }
 
             let a_aabb = *a_bb + *a_pos.0;
-            for (e, _, e_pos, e_bb) in (
+            for (e, _, e_pos, e_bb, e_state) in (
                 &*system_data.entities,
                 &system_data.enemy,
                 &system_data.pos,
                 &system_data.bb,
+                &system_data.state,
             )
                 .join()
             {
-                // Only fight between units with the melee state
-                if *a_state != UnitState::Melee {
+                // Fleeing units don't fight back and can't be attacked
+                if *e_state == UnitState::Flee {
+                    continue;
+                }
+                // Units killed earlier this frame don't fight anymore
+                if is_dead(system_data.health.get(a)) {
+                    break;
+                }
+                if is_dead(system_data.health.get(e)) {
                     continue;
                 }
 
                 let e_aabb = *e_bb + *e_pos.0;
                 if a_aabb.intersects(&*e_aabb) {
                     {
+                        // A staggered unit can't attack and its cooldown is paused
+                        let staggered = matches!(
+                            system_data.effects.get(a),
+                            Some(effects) if effects.is_staggered()
+                        );
                         let a_melee: Option<&mut Melee> = system_data.melee.get_mut(a);
-                        if let Some(melee) = a_melee {
+                        if let (Some(melee), false) = (a_melee, staggered) {
                             melee.cooldown -= dt;
                             if melee.cooldown <= 0.0 {
-                                if reduce_unit_health(
+                                let invulnerable = matches!(
+                                    system_data.effects.get(e),
+                                    Some(effects) if effects.is_invulnerable()
+                                );
+                                let dmg = if invulnerable {
+                                    0.0
+                                } else {
+                                    resisted_damage(
+                                        system_data.resistance.get(e),
+                                        Some(melee.damage_type),
+                                        melee.dmg,
+                                    )
+                                };
+                                let died = reduce_unit_health(
                                     &system_data.entities,
+                                    &mut system_data.stats,
                                     e,
+                                    false,
                                     system_data.health.get_mut(e).unwrap(),
-                                    melee.dmg,
+                                    dmg,
+                                );
+                                if let (Some(stagger), Some(effects), false) = (
+                                    system_data.stagger.get_mut(e),
+                                    system_data.effects.get_mut(e),
+                                    died,
                                 ) {
+                                    // Push the unit away from the attacker
+                                    let direction = if e_pos.0.x >= a_pos.0.x { 1.0 } else { -1.0 };
+                                    stagger.hit(effects, dmg, direction);
+                                }
+                                if died {
                                     // The enemy died
-                                    system_data.updater.insert(
-                                        system_data.entities.create(),
-                                        FloatingText {
-                                            text: "x".to_string(),
-                                            pos: e_pos.0,
-                                            time_alive: 2.0,
-                                        },
+                                    credit_kill(&mut system_data.veterancy, Some(a));
+                                    system_data.events.unit_died(false, e_pos.0);
+                                    spawn_death_effects(
+                                        &system_data.entities,
+                                        &system_data.updater,
+                                        *e_pos,
                                     );
                                 }
 
@@ -120,25 +169,57 @@ impl<'a> System<'a> for MeleeSystem {
                             }
                         }
                     }
+                    if is_dead(system_data.health.get(e)) {
+                        continue;
+                    }
                     {
+                        // A staggered unit can't attack and its cooldown is paused
+                        let staggered = matches!(
+                            system_data.effects.get(e),
+                            Some(effects) if effects.is_staggered()
+                        );
                         let e_melee: Option<&mut Melee> = system_data.melee.get_mut(e);
-                        if let Some(melee) = e_melee {
+                        if let (Some(melee), false) = (e_melee, staggered) {
                             melee.cooldown -= dt;
                             if melee.cooldown <= 0.0 {
-                                if reduce_unit_health(
+                                let invulnerable = matches!(
+                                    system_data.effects.get(a),
+                                    Some(effects) if effects.is_invulnerable()
+                                );
+                                let dmg = if invulnerable {
+                                    0.0
+                                } else {
+                                    resisted_damage(
+                                        system_data.resistance.get(a),
+                                        Some(melee.damage_type),
+                                        melee.dmg,
+                                    )
+                                };
+                                let died = reduce_unit_health(
                                     &system_data.entities,
+                                    &mut system_data.stats,
                                     a,
+                                    true,
                                     system_data.health.get_mut(a).unwrap(),
-                                    melee.dmg,
+                                    dmg,
+                                );
+                                if let (Some(stagger), Some(effects), false) = (
+                                    system_data.stagger.get_mut(a),
+                                    system_data.effects.get_mut(a),
+                                    died,
                                 ) {
+                                    // Push the unit away from the attacker
+                                    let direction = if a_pos.0.x >= e_pos.0.x { 1.0 } else { -1.0 };
+                                    stagger.hit(effects, dmg, direction);
+                                }
+                                if died {
                                     // The ally died
-                                    system_data.updater.insert(
-                                        system_data.entities.create(),
-                                        FloatingText {
-                                            text: "x".to_string(),
-                                            pos: a_pos.0,
-                                            time_alive: 2.0,
-                                        },
+                                    credit_kill(&mut system_data.veterancy, Some(e));
+                                    system_data.events.unit_died(true, a_pos.0);
+                                    spawn_death_effects(
+                                        &system_data.entities,
+                                        &system_data.updater,
+                                        *a_pos,
                                     );
                                 }
 
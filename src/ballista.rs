@@ -0,0 +1,337 @@
+use cgmath::MetricSpace;
+use rand::distributions::{Distribution, Uniform};
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+const ARM_COLOR: u32 = 0x66_39_31;
+const BOLT_COLOR: u32 = 0x66_39_31;
+const BOLT_TRAIL_COLOR: u32 = 0xFF_CB_DB_FC;
+// The walking speed multiplier and seconds of the slow of a unit hit by a bolt
+const BOLT_SLOW: f64 = 0.5;
+const BOLT_SLOW_TIME: f64 = 2.0;
+
+// The arm is aimed when it's pointing less than this many radians away from the target
+const AIM_TOLERANCE: f64 = 0.05;
+// Height in pixels of the pivot of the arm above the terrain surface
+const PIVOT_HEIGHT: f64 = 6.0;
+
+/// The properties of a ballista that don't change while it's standing.
+#[derive(Debug, Copy, Clone)]
+pub struct BallistaSettings {
+    pub range: f64,
+    pub min_range: f64,
+    pub reload_time: f64,
+    pub wind_up_time: f64,
+    // Radians per second
+    pub turn_speed: f64,
+    pub max_speed: f64,
+    pub flight_time: f64,
+    pub strength_variation: f64,
+    pub damage: f64,
+    pub arm_length: f64,
+}
+
+impl Default for BallistaSettings {
+    fn default() -> Self {
+        BallistaSettings {
+            range: 400.0,
+            min_range: 40.0,
+            reload_time: 4.0,
+            wind_up_time: 1.0,
+            turn_speed: 1.5,
+            max_speed: 320.0,
+            flight_time: 1.5,
+            strength_variation: 0.02,
+            damage: 40.0,
+            arm_length: 8.0,
+        }
+    }
+}
+
+/// The aiming of a ballista, the values are the seconds left in the state.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BallistaState {
+    Idle,
+    Tracking,
+    WindingUp(f64),
+    Cooldown(f64),
+}
+
+/// A siege weapon that fires heavy bolts at the nearest enemy in range.
+#[derive(Component, Debug)]
+pub struct Ballista {
+    pub settings: BallistaSettings,
+    pub state: BallistaState,
+    pub angle: f64,
+}
+
+impl Ballista {
+    pub fn new(settings: BallistaSettings) -> Self {
+        Ballista {
+            settings,
+            state: BallistaState::Idle,
+            // Point up towards the enemy castle
+            angle: -std::f64::consts::FRAC_PI_4,
+        }
+    }
+
+    /// Advance the aiming towards the angle needed to hit the target, returns true when it fires.
+    pub fn update(&mut self, dt: f64, target_angle: Option<f64>) -> bool {
+        match (self.state, target_angle) {
+            (BallistaState::Cooldown(left), _) => {
+                self.state = if left > dt {
+                    BallistaState::Cooldown(left - dt)
+                } else {
+                    BallistaState::Idle
+                };
+
+                false
+            }
+            (_, None) => {
+                self.state = BallistaState::Idle;
+
+                false
+            }
+            (BallistaState::Idle, Some(angle)) | (BallistaState::Tracking, Some(angle)) => {
+                self.turn_towards(angle, dt);
+                self.state = if (self.angle - angle).abs() <= AIM_TOLERANCE {
+                    BallistaState::WindingUp(self.settings.wind_up_time)
+                } else {
+                    BallistaState::Tracking
+                };
+
+                false
+            }
+            (BallistaState::WindingUp(left), Some(angle)) => {
+                // Keep following the target while winding up
+                self.turn_towards(angle, dt);
+                if left > dt {
+                    self.state = BallistaState::WindingUp(left - dt);
+
+                    false
+                } else {
+                    self.state = BallistaState::Cooldown(self.settings.reload_time);
+
+                    true
+                }
+            }
+        }
+    }
+
+    fn turn_towards(&mut self, angle: f64, dt: f64) {
+        let max_turn = self.settings.turn_speed * dt;
+        self.angle += (angle - self.angle).clamp(-max_turn, max_turn);
+    }
+}
+
+/// Build an allied ballista on the terrain surface at the horizontal position.
+///
+/// Returns false when there is no ground to build on.
+pub fn build_ballista(world: &mut World, x: f64) -> bool {
+    let surface = match world
+        .read_resource::<Terrain>()
+        .surface_below(Point::new(x, 0.0))
+    {
+        Some(surface) => surface,
+        None => return false,
+    };
+
+    world
+        .create_entity()
+        .with(Ally)
+        .with(Ballista::new(BallistaSettings::default()))
+        .with(Point::new(x, surface.y - PIVOT_HEIGHT))
+        .with(Line::new(ARM_COLOR))
+        .build();
+
+    true
+}
+
+#[derive(SystemData)]
+pub struct BallistaSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    grav: Read<'a, Gravity>,
+    enemy: ReadStorage<'a, Enemy>,
+    wpos: ReadStorage<'a, WorldPosition>,
+    bb: ReadStorage<'a, BoundingBox>,
+    walk: ReadStorage<'a, Walk>,
+    dest: ReadStorage<'a, Destination>,
+    state: ReadStorage<'a, UnitState>,
+    pos: ReadStorage<'a, Point>,
+    ballista: WriteStorage<'a, Ballista>,
+    line: WriteStorage<'a, Line>,
+    stats: Write<'a, Statistics>,
+    updater: Read<'a, LazyUpdate>,
+}
+
+pub struct BallistaSystem;
+impl<'a> System<'a> for BallistaSystem {
+    type SystemData = BallistaSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let grav = system_data.grav.0;
+
+        for (e, pos, ballista, line) in (
+            &*system_data.entities,
+            &system_data.pos,
+            &mut system_data.ballista,
+            &mut system_data.line,
+        )
+            .join()
+        {
+            let settings = ballista.settings;
+
+            // Find the nearest enemy in range, aiming where it will be when the bolt arrives
+            let mut closest: Option<(f64, Point)> = None;
+            for (_, epos, bb, walk, dest, state) in (
+                &system_data.enemy,
+                &system_data.wpos,
+                &system_data.bb,
+                &system_data.walk,
+                &system_data.dest,
+                &system_data.state,
+            )
+                .join()
+            {
+                let mut target = epos.0;
+                target.x += bb.width() / 2.0;
+                target.y += bb.height() / 2.0;
+
+                let dist = pos.distance(*target);
+                if dist < settings.min_range || dist > settings.range {
+                    continue;
+                }
+
+                if *state == UnitState::Walk {
+                    target.x += walk.speed * settings.flight_time * (dest.0 - target.x).signum();
+                }
+
+                if !matches!(closest, Some((closest_dist, _)) if closest_dist <= dist) {
+                    closest = Some((dist, target));
+                }
+            }
+
+            // Refuse to fire when the target can't be reached
+            let vel = closest
+                .map(|(_, target)| {
+                    launch_velocity_for(
+                        (target.x - pos.x, target.y - pos.y),
+                        settings.flight_time,
+                        grav,
+                        0.0,
+                    )
+                })
+                .filter(|vel| vel.length() <= settings.max_speed);
+
+            let fire = ballista.update(dt, vel.map(|vel| vel.y.atan2(vel.x)));
+
+            // Point the arm in the direction it's aiming
+            line.p1 = pos.as_usize();
+            line.p2 = Point::new(
+                (pos.x + ballista.angle.cos() * settings.arm_length).max(0.0),
+                (pos.y + ballista.angle.sin() * settings.arm_length).max(0.0),
+            )
+            .as_usize();
+
+            if let (true, Some(vel)) = (fire, vel) {
+                let strength = if settings.strength_variation > 0.0 {
+                    Uniform::new_inclusive(
+                        1.0 - settings.strength_variation,
+                        1.0 + settings.strength_variation,
+                    )
+                    .sample(&mut rand::thread_rng())
+                } else {
+                    1.0
+                };
+
+                let bolt = system_data.entities.create();
+                system_data.updater.insert(bolt, Projectile);
+                system_data.updater.insert(bolt, Owner(e));
+                system_data.updater.insert(bolt, WorldPosition(*pos));
+                system_data
+                    .updater
+                    .insert(bolt, Velocity::new(vel.x * strength, vel.y * strength));
+                system_data.updater.insert(bolt, Arrow(9.0));
+                system_data.updater.insert(bolt, Line::new(BOLT_COLOR));
+                system_data
+                    .updater
+                    .insert(bolt, Trail::new(6, 0.05, BOLT_TRAIL_COLOR));
+                system_data.updater.insert(bolt, Damage(settings.damage));
+                system_data.updater.insert(bolt, DamageType::Pierce);
+                system_data.updater.insert(
+                    bolt,
+                    StatusEffectOnHit(StatusEffect::slow(BOLT_SLOW, BOLT_SLOW_TIME)),
+                );
+                system_data.updater.insert(
+                    bolt,
+                    ProjectileBoundingBox(BoundingBox::new(
+                        Point::new(0.0, 0.0),
+                        Point::new(1.0, 1.0),
+                    )),
+                );
+                system_data.updater.insert(bolt, IgnoreCollision::Ally);
+
+                system_data.stats.projectiles_fired += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ballista() -> Ballista {
+        Ballista::new(BallistaSettings {
+            reload_time: 2.0,
+            wind_up_time: 1.0,
+            turn_speed: 1.0,
+            ..BallistaSettings::default()
+        })
+    }
+
+    #[test]
+    fn idle_without_target() {
+        let mut ballista = ballista();
+
+        assert!(!ballista.update(0.1, None));
+        assert_eq!(ballista.state, BallistaState::Idle);
+    }
+
+    #[test]
+    fn tracks_winds_up_fires_and_reloads() {
+        let mut ballista = ballista();
+        let target = ballista.angle + 0.5;
+
+        // The arm turns one radian per second
+        assert!(!ballista.update(0.25, Some(target)));
+        assert_eq!(ballista.state, BallistaState::Tracking);
+        assert!(!ballista.update(0.25, Some(target)));
+        assert_eq!(ballista.state, BallistaState::WindingUp(1.0));
+
+        assert!(!ballista.update(0.5, Some(target)));
+        assert!(ballista.update(0.5, Some(target)));
+        assert_eq!(ballista.state, BallistaState::Cooldown(2.0));
+
+        // Reloading doesn't fire even with a target
+        assert!(!ballista.update(1.5, Some(target)));
+        assert!(!ballista.update(0.5, Some(target)));
+        assert_eq!(ballista.state, BallistaState::Idle);
+    }
+
+    #[test]
+    fn losing_target_stops_winding_up() {
+        let mut ballista = ballista();
+        let target = ballista.angle;
+
+        ballista.update(0.1, Some(target));
+        assert_eq!(ballista.state, BallistaState::WindingUp(1.0));
+
+        assert!(!ballista.update(0.1, None));
+        assert_eq!(ballista.state, BallistaState::Idle);
+    }
+}
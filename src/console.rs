@@ -0,0 +1,425 @@
+use minifb::{InputCallback, Key, KeyRepeat, Window};
+use specs::prelude::*;
+// The explicit import shadows the Result alias of minifb that comes with the glob import
+use std::result::Result;
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use super::*;
+
+// Amount of output lines kept and shown above the prompt
+const MAX_OUTPUT_LINES: usize = 10;
+
+type Handler = fn(&mut World, &[&str]) -> Result<Vec<String>, String>;
+
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    handler: Handler,
+}
+
+// Passes the characters typed in the window to the console
+struct CharInput(Rc<RefCell<String>>);
+
+impl InputCallback for CharInput {
+    fn add_char(&mut self, uni_char: u32) {
+        if let Some(c) = std::char::from_u32(uni_char) {
+            // The backtick is used to toggle the console
+            if !c.is_control() && c != '`' {
+                self.0.borrow_mut().push(c);
+            }
+        }
+    }
+}
+
+/// A debug console that is toggled with the backtick key.
+pub struct Console {
+    open: bool,
+    typed: Rc<RefCell<String>>,
+    line: String,
+    output: VecDeque<String>,
+    history: Vec<String>,
+    history_index: usize,
+    // Lines entered this frame, they are executed at a fixed point in the game loop
+    queued: Vec<String>,
+    commands: Vec<Command>,
+}
+
+impl Console {
+    pub fn new(window: &mut Window) -> Self {
+        let typed = Rc::new(RefCell::new(String::new()));
+        window.set_input_callback(Box::new(CharInput(typed.clone())));
+
+        Console::with_commands(typed)
+    }
+
+    // Create the console with all the commands, the characters are typed into the shared string
+    fn with_commands(typed: Rc<RefCell<String>>) -> Self {
+        let mut console = Console {
+            open: false,
+            typed,
+            line: String::new(),
+            output: VecDeque::new(),
+            history: Vec::new(),
+            history_index: 0,
+            queued: Vec::new(),
+            commands: Vec::new(),
+        };
+
+        console.register("spawn", "spawn <archer|soldier>", spawn_command);
+        console.register("kill_all", "kill_all <allies|enemies>", kill_all_command);
+        console.register(
+            "set",
+            "set <gravity <value>|edge <walls|fall_off|wrap>>",
+            set_command,
+        );
+        console.register(
+            "build",
+            "build <ballista|catapult|tower> <x>",
+            build_command,
+        );
+        console.register("garrison", "garrison", garrison_command);
+        console.register("ungarrison", "ungarrison", ungarrison_command);
+        console.register("stats", "stats", stats_command);
+        console.register("mods", "mods", mods_command);
+
+        console
+    }
+
+    fn register(&mut self, name: &'static str, usage: &'static str, handler: Handler) {
+        self.commands.push(Command {
+            name,
+            usage,
+            handler,
+        });
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Toggle the console and edit the line that is being typed.
+    pub fn handle_keys(&mut self, window: &Window) {
+        let typed = self.typed.replace(String::new());
+
+        if window.is_key_pressed(Key::Backquote, KeyRepeat::No) {
+            self.open = !self.open;
+            return;
+        }
+        if !self.open {
+            return;
+        }
+
+        self.line.push_str(&typed);
+
+        if window.is_key_pressed(Key::Backspace, KeyRepeat::Yes) {
+            self.line.pop();
+        }
+        if window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+            self.complete();
+        }
+        if window.is_key_pressed(Key::Up, KeyRepeat::No) && self.history_index > 0 {
+            self.history_index -= 1;
+            self.line = self.history[self.history_index].clone();
+        }
+        if window.is_key_pressed(Key::Down, KeyRepeat::No)
+            && self.history_index < self.history.len()
+        {
+            self.history_index += 1;
+            self.line = self
+                .history
+                .get(self.history_index)
+                .cloned()
+                .unwrap_or_default();
+        }
+        if window.is_key_pressed(Key::Enter, KeyRepeat::No) {
+            let line = std::mem::take(&mut self.line);
+            if !line.trim().is_empty() {
+                self.history.push(line.clone());
+                self.queued.push(line);
+            }
+            self.history_index = self.history.len();
+        }
+    }
+
+    /// Execute the lines that were entered since the last call.
+    pub fn run_queued(&mut self, world: &mut World) {
+        for line in std::mem::take(&mut self.queued) {
+            self.print(format!("> {}", line));
+
+            let args: Vec<&str> = line.split_whitespace().collect();
+            if args[0] == "help" {
+                let usages: Vec<&str> = self.commands.iter().map(|command| command.usage).collect();
+                usages
+                    .into_iter()
+                    .for_each(|usage| self.print(usage.to_string()));
+                continue;
+            }
+
+            let result = match self.commands.iter().find(|command| command.name == args[0]) {
+                Some(command) => (command.handler)(world, &args[1..])
+                    .map_err(|err| format!("{}, usage: {}", err, command.usage)),
+                None => Err(format!("unknown command \"{}\"", args[0])),
+            };
+
+            match result {
+                Ok(lines) => lines.into_iter().for_each(|line| self.print(line)),
+                Err(err) => self.print(format!("error: {}", err)),
+            }
+        }
+    }
+
+    /// The output followed by the prompt, from top to bottom.
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.output
+            .iter()
+            .cloned()
+            .chain(std::iter::once(format!("> {}_", self.line)))
+    }
+
+    fn print(&mut self, line: String) {
+        if self.output.len() == MAX_OUTPUT_LINES {
+            self.output.pop_front();
+        }
+        self.output.push_back(line);
+    }
+
+    // Complete the name of the command or show all the options when there are more
+    fn complete(&mut self) {
+        if self.line.contains(' ') {
+            return;
+        }
+
+        let matches: Vec<&str> = self
+            .commands
+            .iter()
+            .map(|command| command.name)
+            .filter(|name| name.starts_with(self.line.as_str()))
+            .collect();
+        match matches.as_slice() {
+            [] => (),
+            [name] => self.line = format!("{} ", name),
+            _ => self.print(matches.join(" ")),
+        }
+    }
+}
+
+// Debug units aren't recruited so they don't show up in the statistics
+fn spawn_command(world: &mut World, args: &[&str]) -> Result<Vec<String>, String> {
+    let spawned = match args {
+        ["archer"] => spawn_archer(world, ARCHER_HEALTH, None),
+        ["soldier"] => spawn_soldier(world),
+        _ => return Err("unknown unit".to_string()),
+    };
+
+    if spawned {
+        Ok(vec![format!("spawned {}", args[0])])
+    } else {
+        Err("the castle door is blocked".to_string())
+    }
+}
+
+fn kill_all_command(world: &mut World, args: &[&str]) -> Result<Vec<String>, String> {
+    let is_ally = match args {
+        ["allies"] => true,
+        ["enemies"] => false,
+        _ => return Err("unknown side".to_string()),
+    };
+
+    let entities = world.entities();
+    let updater = world.read_resource::<LazyUpdate>();
+    let ally = world.read_storage::<Ally>();
+    let enemy = world.read_storage::<Enemy>();
+    let pos = world.read_storage::<WorldPosition>();
+    let mut health = world.write_storage::<Health>();
+    let mut stats = world.write_resource::<Statistics>();
+    let mut events = world.write_resource::<EventLog>();
+
+    // The units die like any other unit, so the statistics and morale see the deaths
+    let mut killed = 0;
+    for (unit, pos, health, ally, enemy) in
+        (&entities, &pos, &mut health, ally.maybe(), enemy.maybe()).join()
+    {
+        let on_side = if is_ally {
+            ally.is_some()
+        } else {
+            enemy.is_some()
+        };
+        if !on_side {
+            continue;
+        }
+
+        let dmg = health.0;
+        if !reduce_unit_health(&entities, &mut stats, unit, is_ally, health, dmg) {
+            continue;
+        }
+        events.unit_died(is_ally, pos.0);
+        spawn_death_effects(&entities, &updater, *pos);
+        killed += 1;
+    }
+
+    Ok(vec![format!("killed {} units", killed)])
+}
+
+fn set_command(world: &mut World, args: &[&str]) -> Result<Vec<String>, String> {
+    match args {
+        ["gravity", value] => {
+            let value = value
+                .parse()
+                .map_err(|_| format!("invalid value \"{}\"", value))?;
+            world.write_resource::<Gravity>().0 = value;
+
+            Ok(vec![format!("gravity set to {}", value)])
+        }
+        ["edge", value] => {
+            let edge: EdgeBehavior = value.parse()?;
+            *world.write_resource::<EdgeBehavior>() = edge;
+
+            Ok(vec![format!("edge behavior set to {:?}", edge)])
+        }
+        _ => Err("unknown setting".to_string()),
+    }
+}
+
+fn build_command(world: &mut World, args: &[&str]) -> Result<Vec<String>, String> {
+    let (name, x) = match args {
+        [name, x] => (*name, *x),
+        _ => return Err("unknown building".to_string()),
+    };
+    let build: fn(&mut World, f64) -> bool = match name {
+        "ballista" => build_ballista,
+        "catapult" => build_catapult,
+        "tower" => build_arrow_tower,
+        _ => return Err("unknown building".to_string()),
+    };
+
+    let x = x
+        .parse::<f64>()
+        .map_err(|_| format!("invalid position \"{}\"", x))?;
+    if build(world, x) {
+        Ok(vec![format!("built {} at {}", name, x)])
+    } else {
+        Err("there is no ground to build on".to_string())
+    }
+}
+
+fn garrison_command(world: &mut World, _args: &[&str]) -> Result<Vec<String>, String> {
+    match garrison_archers(world) {
+        0 => Err("no archers at the castle door or the castle is full".to_string()),
+        entered => Ok(vec![format!("{} archers entered the castle", entered)]),
+    }
+}
+
+fn ungarrison_command(world: &mut World, _args: &[&str]) -> Result<Vec<String>, String> {
+    if ungarrison_archer(world) {
+        Ok(vec!["archer left the castle".to_string()])
+    } else {
+        Err("no archers in the castle or the door is blocked".to_string())
+    }
+}
+
+fn stats_command(world: &mut World, _args: &[&str]) -> Result<Vec<String>, String> {
+    Ok(world.read_resource::<Statistics>().summary())
+}
+
+fn mods_command(world: &mut World, _args: &[&str]) -> Result<Vec<String>, String> {
+    let mods = world.read_resource::<Mods>();
+    if mods.mods_loaded().is_empty() {
+        Ok(vec!["no mods loaded".to_string()])
+    } else {
+        Ok(mods.mods_loaded().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+        world.insert(Terrain::new((100, 400)));
+        world.insert(Gravity(98.1));
+        world.insert(EdgeBehavior::default());
+        world.insert(Images(
+            vec![("ally-archer1".to_string(), 0)].into_iter().collect(),
+        ));
+        world.insert(Statistics::default());
+        world.insert(EventLog::default());
+
+        world
+    }
+
+    // Execute the line and return the output without the echoed line
+    fn run(console: &mut Console, world: &mut World, line: &str) -> Vec<String> {
+        console.output.clear();
+        console.queued.push(line.to_string());
+        console.run_queued(world);
+
+        console.output.iter().skip(1).cloned().collect()
+    }
+
+    #[test]
+    fn unknown_commands_and_arguments_are_reported() {
+        let mut console = Console::with_commands(Rc::default());
+        let mut world = world();
+
+        assert_eq!(
+            run(&mut console, &mut world, "fly away"),
+            ["error: unknown command \"fly\""]
+        );
+        assert_eq!(
+            run(&mut console, &mut world, "spawn"),
+            ["error: unknown unit, usage: spawn <archer|soldier>"]
+        );
+        assert_eq!(
+            run(&mut console, &mut world, "spawn archer soldier"),
+            ["error: unknown unit, usage: spawn <archer|soldier>"]
+        );
+        assert_eq!(
+            run(&mut console, &mut world, "set gravity"),
+            ["error: unknown setting, usage: set <gravity <value>|edge <walls|fall_off|wrap>>"]
+        );
+        assert_eq!(
+            run(&mut console, &mut world, "build tower"),
+            ["error: unknown building, usage: build <ballista|catapult|tower> <x>"]
+        );
+    }
+
+    #[test]
+    fn settings_are_changed() {
+        let mut console = Console::with_commands(Rc::default());
+        let mut world = world();
+
+        assert_eq!(
+            run(&mut console, &mut world, "set gravity 50"),
+            ["gravity set to 50"]
+        );
+        assert_eq!(world.read_resource::<Gravity>().0, 50.0);
+        assert!(run(&mut console, &mut world, "set gravity heavy")[0]
+            .starts_with("error: invalid value \"heavy\""));
+        assert_eq!(world.read_resource::<Gravity>().0, 50.0);
+
+        assert_eq!(
+            run(&mut console, &mut world, "set edge wrap"),
+            ["edge behavior set to Wrap"]
+        );
+        assert_eq!(*world.read_resource::<EdgeBehavior>(), EdgeBehavior::Wrap);
+        assert!(run(&mut console, &mut world, "set edge bounce")[0]
+            .starts_with("error: unknown edge behavior \"bounce\""));
+        assert_eq!(*world.read_resource::<EdgeBehavior>(), EdgeBehavior::Wrap);
+    }
+
+    #[test]
+    fn spawned_units_are_not_recruited() {
+        let mut console = Console::with_commands(Rc::default());
+        let mut world = world();
+
+        assert_eq!(
+            run(&mut console, &mut world, "spawn archer"),
+            ["spawned archer"]
+        );
+        world.maintain();
+        assert_eq!(world.read_storage::<Ally>().join().count(), 1);
+        assert_eq!(world.read_resource::<Statistics>().units_recruited, 0);
+    }
+}
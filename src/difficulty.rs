@@ -0,0 +1,102 @@
+use std::str::FromStr;
+
+/// How hard the enemy castle is to beat, selected before the battle starts.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!(
+                "unknown difficulty \"{}\", expected easy, normal or hard",
+                s
+            )),
+        }
+    }
+}
+
+/// Multipliers applied to the base values of the enemy units and turrets.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tuning {
+    pub difficulty: Difficulty,
+    pub enemy_health: f64,
+    pub enemy_damage: f64,
+    /// Multiplier of the time between shots of enemy turrets, higher is slower.
+    pub enemy_turret_delay: f64,
+}
+
+impl Tuning {
+    pub fn new(difficulty: Difficulty) -> Self {
+        let (enemy_health, enemy_damage, enemy_turret_delay) = match difficulty {
+            Difficulty::Easy => (0.75, 0.75, 1.5),
+            // Normal always uses the base values
+            Difficulty::Normal => (1.0, 1.0, 1.0),
+            Difficulty::Hard => (1.5, 1.25, 0.75),
+        };
+
+        Tuning {
+            difficulty,
+            enemy_health,
+            enemy_damage,
+            enemy_turret_delay,
+        }
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::new(Difficulty::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_uses_the_base_values() {
+        let tuning = Tuning::default();
+        assert_eq!(tuning.difficulty, Difficulty::Normal);
+        assert_eq!(
+            (
+                tuning.enemy_health,
+                tuning.enemy_damage,
+                tuning.enemy_turret_delay
+            ),
+            (1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn harder_difficulties_make_the_enemy_stronger() {
+        let easy = Tuning::new(Difficulty::Easy);
+        let normal = Tuning::new(Difficulty::Normal);
+        let hard = Tuning::new(Difficulty::Hard);
+
+        assert!(easy.enemy_health < normal.enemy_health);
+        assert!(normal.enemy_health < hard.enemy_health);
+        assert!(easy.enemy_damage < normal.enemy_damage);
+        assert!(normal.enemy_damage < hard.enemy_damage);
+        // The turrets shoot faster so the delay goes down
+        assert!(easy.enemy_turret_delay > normal.enemy_turret_delay);
+        assert!(normal.enemy_turret_delay > hard.enemy_turret_delay);
+    }
+
+    #[test]
+    fn difficulty_is_parsed_case_insensitive() {
+        assert_eq!("easy".parse(), Ok(Difficulty::Easy));
+        assert_eq!("Normal".parse(), Ok(Difficulty::Normal));
+        assert_eq!("HARD".parse(), Ok(Difficulty::Hard));
+        assert!("impossible".parse::<Difficulty>().is_err());
+    }
+}
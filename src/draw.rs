@@ -8,10 +8,16 @@ use std::error::Error;
 use std::time::Duration;
 
 use crate::geom::*;
+use crate::projectile::Trail;
 use crate::terrain::*;
 
 const GREEN_BAR_COLOR: u32 = 0xFF_6A_BE_30;
 const RED_BAR_COLOR: u32 = 0xFF_AC_32_33;
+const LOST_BAR_COLOR: u32 = 0xFF_FB_F2_36;
+
+// Objects higher above the ground than this don't cast a shadow
+const SHADOW_MAX_HEIGHT: f64 = 100.0;
+const SHADOW_MAX_RADIUS: f64 = 3.0;
 
 #[derive(Component, Debug, Copy, Clone)]
 pub struct PixelParticle {
@@ -120,6 +126,8 @@ impl<'a> System<'a> for AnimSystem {
 
 pub struct Render {
     background: Vec<u32>,
+    // The terrain drawn on top of the background, only updated where the terrain changed
+    terrain_cache: Vec<u32>,
 
     blit_buffers: Vec<(String, BlitBuffer)>,
     anim_buffers: Vec<(String, AnimationBlitBuffer)>,
@@ -131,7 +139,8 @@ pub struct Render {
 impl Render {
     pub fn new(size: (usize, usize)) -> Self {
         Render {
-            background: vec![0; (size.0 * size.1) as usize],
+            background: vec![0; size.0 * size.1],
+            terrain_cache: vec![0; size.0 * size.1],
 
             width: size.0,
             height: size.1,
@@ -141,25 +150,38 @@ impl Render {
         }
     }
 
-    pub fn draw_terrain_and_background(&mut self, buffer: &mut Vec<u32>, terrain: &Terrain) {
-        for (output, (bg, terrain)) in buffer
-            .iter_mut()
-            .zip(self.background.iter().zip(&terrain.buffer))
-        {
-            if (*terrain & 0xFF_FF_FF) != 0xFF_00_FF {
-                // The terrain doesn't needs to be cleared
-                *output = *terrain;
-                continue;
+    /// Draw the terrain with the background behind it.
+    ///
+    /// Only the part of the terrain that changed since the last call is combined with the
+    /// background again, the rest is copied from the previous result.
+    pub fn draw_terrain_and_background(&mut self, buffer: &mut [u32], terrain: &mut Terrain) {
+        if let Some((x1, y1, x2, y2)) = terrain.take_dirty() {
+            for y in y1..y2 {
+                let row = (x1 + y * self.width)..(x2 + y * self.width);
+                for (output, (bg, terrain)) in self.terrain_cache[row.clone()].iter_mut().zip(
+                    self.background[row.clone()]
+                        .iter()
+                        .zip(&terrain.buffer[row]),
+                ) {
+                    if (*terrain & 0xFF_FF_FF) != 0xFF_00_FF {
+                        // The terrain doesn't needs to be cleared
+                        *output = *terrain;
+                        continue;
+                    }
+                    *output = *bg;
+                }
             }
-            *output = *bg;
         }
+
+        buffer.copy_from_slice(&self.terrain_cache);
     }
 
     pub fn draw_healthbar(
         &mut self,
-        buffer: &mut Vec<u32>,
+        buffer: &mut [u32],
         pos: Point2<usize>,
         health_ratio: f64,
+        delayed_ratio: f64,
         width: usize,
     ) {
         if pos.x >= self.width || pos.y >= self.height {
@@ -174,22 +196,93 @@ impl Render {
             width
         };
         let health = pos.x + (health_ratio * width as f64) as usize;
+        let delayed = (pos.x + (delayed_ratio * width as f64) as usize).max(health);
 
         // Draw the green bar
         for x in pos.x..health {
             buffer[x + y] = GREEN_BAR_COLOR;
         }
 
+        // Draw the recently lost health
+        for x in health..delayed {
+            buffer[x + y] = LOST_BAR_COLOR;
+        }
+
         // Draw the red bar
         let max = pos.x + width;
-        for x in health..max {
+        for x in delayed..max {
             buffer[x + y] = RED_BAR_COLOR;
         }
     }
 
+    /// Draw a dithered shadow on the terrain surface below the position.
+    ///
+    /// The shadow gets smaller and lighter the higher the position is above the ground, and only
+    /// darkens pixels that are part of the terrain.
+    pub fn draw_shadow(&mut self, buffer: &mut [u32], terrain: &Terrain, pos: Point) {
+        let surface = match terrain.surface_below(pos) {
+            Some(surface) => surface,
+            None => return,
+        };
+
+        let height = surface.y - pos.y;
+        if height > SHADOW_MAX_HEIGHT {
+            return;
+        }
+
+        let radius = (SHADOW_MAX_RADIUS * (1.0 - height / SHADOW_MAX_HEIGHT)).round() as usize;
+        // Skip more pixels of the dither pattern when the object is high up
+        let density = if height > SHADOW_MAX_HEIGHT / 2.0 {
+            4
+        } else {
+            2
+        };
+
+        let center = surface.as_usize();
+        for dy in 0..2 {
+            let y = center.y + dy;
+            if y >= self.height {
+                return;
+            }
+
+            // Make the bottom row narrower to round the shadow off
+            let row_radius = radius.saturating_sub(dy);
+            for x in center.x.saturating_sub(row_radius)..=center.x + row_radius {
+                if x >= self.width || (x + y) % density != 0 {
+                    continue;
+                }
+
+                let index = x + y * self.width;
+                if (terrain.buffer[index] & 0xFF_FF_FF) != 0xFF_00_FF {
+                    buffer[index] = (buffer[index] >> 1) & 0x7F_7F_7F;
+                }
+            }
+        }
+    }
+
+    /// Draw the trail as lines between the remembered positions, getting darker with age.
+    pub fn draw_trail(&mut self, buffer: &mut [u32], trail: &Trail) {
+        let mut newer: Option<(i32, i32)> = None;
+        for (age, point) in trail.points().enumerate() {
+            if let Some(newer) = newer {
+                let color = darken(trail.color, trail.length - age, trail.length + 1);
+                // The trail can start outside of the screen, only draw the visible pixels
+                for (x, y) in Bresenham::new(newer, point) {
+                    if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+                        continue;
+                    }
+
+                    buffer[x as usize + y as usize * self.width] = color;
+                }
+            }
+
+            newer = Some(point);
+        }
+    }
+
     pub fn draw_foreground(
         &mut self,
-        buffer: &mut Vec<u32>,
+        buffer: &mut [u32],
         sprite: &Sprite,
     ) -> Result<(), Box<dyn Error>> {
         let buf = &self.blit_buffers[sprite.img_ref()].1;
@@ -200,9 +293,29 @@ impl Render {
         Ok(())
     }
 
+    /// Draw a sprite into the terrain with the bottom center of the sprite at the position.
+    ///
+    /// The sprite becomes part of the terrain, so it can be destroyed like the rest of the level.
+    pub fn draw_sprite_terrain(
+        &mut self,
+        terrain: &mut Terrain,
+        img_ref: usize,
+        pos: (i32, i32),
+    ) -> Result<(), Box<dyn Error>> {
+        let buf = &self.blit_buffers[img_ref].1;
+
+        let pos = (pos.0 - buf.size().0 / 2, pos.1 - buf.size().1);
+
+        let size = self.size();
+        buf.blit(&mut terrain.buffer, size.0, pos);
+        terrain.mark_dirty(pos, buf.size());
+
+        Ok(())
+    }
+
     pub fn draw_foreground_anim(
         &mut self,
-        buffer: &mut Vec<u32>,
+        buffer: &mut [u32],
         anim: &Anim,
     ) -> Result<(), Box<dyn Error>> {
         let buf = &self.anim_buffers[anim.img_ref()].1;
@@ -213,7 +326,7 @@ impl Render {
         Ok(())
     }
 
-    pub fn draw_foreground_pixel(&mut self, buffer: &mut Vec<u32>, pos: Point2<usize>, color: u32) {
+    pub fn draw_foreground_pixel(&mut self, buffer: &mut [u32], pos: Point2<usize>, color: u32) {
         if pos.x >= self.width || pos.y >= self.height {
             return;
         }
@@ -221,9 +334,22 @@ impl Render {
         buffer[pos.x + pos.y * self.width] = color;
     }
 
+    /// Draw a vertical line over the whole height of the screen with gaps in between.
+    pub fn draw_dashed_vertical_line(
+        &mut self,
+        buffer: &mut [u32],
+        x: usize,
+        dash: usize,
+        color: u32,
+    ) {
+        for y in (0..self.height).filter(|y| y % (dash * 2) < dash) {
+            self.draw_foreground_pixel(buffer, Point2::new(x, y), color);
+        }
+    }
+
     pub fn draw_foreground_line(
         &mut self,
-        buffer: &mut Vec<u32>,
+        buffer: &mut [u32],
         p1: Point2<usize>,
         p2: Point2<usize>,
         color: u32,
@@ -254,7 +380,11 @@ impl Render {
         pos.1 -= buf.size().1 / 2;
 
         let size = self.size();
-        buf.blit(&mut terrain.buffer, size.0, pos);
+        terrain.remove_with(mask.pos, buf.size(), |buffer| buf.blit(buffer, size.0, pos));
+
+        // Leave scorch marks around the crater
+        let radius = f64::from(buf.size().0.max(buf.size().1)) / 2.0;
+        terrain.scorch(mask.pos, radius);
 
         Ok(())
     }
@@ -264,6 +394,7 @@ impl Render {
 
         let size = self.size();
         buf.blit(&mut terrain.buffer, size.0, (0, 0));
+        terrain.mark_dirty((0, 0), buf.size());
     }
 
     pub fn draw_background_from_memory(&mut self, bytes: &[u8]) {
@@ -303,3 +434,13 @@ impl Render {
         self.anim_buffers.len() - 1
     }
 }
+
+/// Scale the RGB channels of the color with a fraction.
+fn darken(color: u32, numerator: usize, denominator: usize) -> u32 {
+    let channel = |shift: u32| {
+        let value = (color >> shift) & 0xFF;
+        ((value as usize * numerator / denominator) as u32) << shift
+    };
+
+    0xFF_00_00_00 | channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,148 @@
+use std::f64::consts::PI;
+
+/// Curves mapping a normalized time between 0.0 and 1.0 to a progress value.
+///
+/// All curves start at 0.0 and end at 1.0, only the elastic curve overshoots in between.
+// Not every curve is used by the game yet
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticOut,
+}
+
+impl Easing {
+    /// Get the progress at the time, the time is clamped between 0.0 and 1.0.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => t * (2.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    -1.0 + (4.0 - 2.0 * t) * t
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => {
+                let f = t - 1.0;
+                f * f * f + 1.0
+            }
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    let f = 2.0 * t - 2.0;
+                    0.5 * f * f * f + 1.0
+                }
+            }
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f64.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+        }
+    }
+}
+
+/// Interpolate between two values over a duration in seconds.
+///
+/// The value is calculated from the elapsed time instead of being incremented every frame, so the
+/// animation looks the same regardless of the frame rate.
+#[derive(Debug, Copy, Clone)]
+pub struct Tween {
+    pub start: f64,
+    pub end: f64,
+    pub duration: f64,
+    pub easing: Easing,
+}
+
+impl Tween {
+    pub fn new(start: f64, end: f64, duration: f64, easing: Easing) -> Self {
+        Tween {
+            start,
+            end,
+            duration,
+            easing,
+        }
+    }
+
+    /// The value after the amount of seconds have elapsed since the start.
+    pub fn value_at(&self, elapsed: f64) -> f64 {
+        if self.duration <= 0.0 {
+            return self.end;
+        }
+
+        self.start + (self.end - self.start) * self.easing.apply(elapsed / self.duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Easing; 8] = [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::ElasticOut,
+    ];
+
+    #[test]
+    fn curves_start_at_zero_and_end_at_one() {
+        for easing in ALL.iter() {
+            assert!(easing.apply(0.0).abs() < 1e-9, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-9, "{:?}", easing);
+            // The time is clamped
+            assert!(easing.apply(-1.0).abs() < 1e-9, "{:?}", easing);
+            assert!((easing.apply(2.0) - 1.0).abs() < 1e-9, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn curves_only_go_up_except_elastic() {
+        for easing in ALL.iter().filter(|easing| **easing != Easing::ElasticOut) {
+            let mut prev = easing.apply(0.0);
+            for step in 1..=100 {
+                let value = easing.apply(f64::from(step) / 100.0);
+                assert!(value >= prev, "{:?} goes down at step {}", easing, step);
+                prev = value;
+            }
+        }
+
+        // The elastic curve overshoots the end before settling
+        let max = (1..100)
+            .map(|step| Easing::ElasticOut.apply(f64::from(step) / 100.0))
+            .fold(0.0, f64::max);
+        assert!(max > 1.0);
+    }
+
+    #[test]
+    fn tween_interpolates_over_the_duration() {
+        let tween = Tween::new(10.0, 20.0, 2.0, Easing::Linear);
+        assert_eq!(tween.value_at(0.0), 10.0);
+        assert_eq!(tween.value_at(1.0), 15.0);
+        assert_eq!(tween.value_at(5.0), 20.0);
+
+        assert_eq!(
+            Tween::new(10.0, 20.0, 0.0, Easing::Linear).value_at(0.0),
+            20.0
+        );
+    }
+}
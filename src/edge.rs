@@ -0,0 +1,234 @@
+use specs::prelude::*;
+use std::str::FromStr;
+
+use crate::ai::Ally;
+use crate::events::EventLog;
+use crate::geom::WorldPosition;
+use crate::physics::Velocity;
+use crate::projectile::{Projectile, Trail};
+use crate::stats::Statistics;
+use crate::terrain::Terrain;
+use crate::unit::Walk;
+
+/// What happens to units and projectiles reaching the left or right side of the level.
+///
+/// Everything that falls below the bottom of the level is always removed.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum EdgeBehavior {
+    /// Projectiles bounce back from invisible walls and units can't walk past them.
+    Walls,
+    /// Nothing stops at the sides, bodies leaving the level fall until they are removed.
+    #[default]
+    FallOff,
+    /// Projectiles leaving one side come back in on the other side.
+    Wrap,
+}
+
+impl FromStr for EdgeBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "walls" => Ok(EdgeBehavior::Walls),
+            "fall_off" => Ok(EdgeBehavior::FallOff),
+            "wrap" => Ok(EdgeBehavior::Wrap),
+            _ => Err(format!(
+                "unknown edge behavior \"{}\", expected walls, fall_off or wrap",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct EdgeSystemData<'a> {
+    entities: Entities<'a>,
+    edge: Read<'a, EdgeBehavior>,
+    terrain: Read<'a, Terrain>,
+    proj: ReadStorage<'a, Projectile>,
+    walk: ReadStorage<'a, Walk>,
+    ally: ReadStorage<'a, Ally>,
+    vel: WriteStorage<'a, Velocity>,
+    pos: WriteStorage<'a, WorldPosition>,
+    trail: WriteStorage<'a, Trail>,
+    stats: Write<'a, Statistics>,
+    events: Write<'a, EventLog>,
+}
+
+pub struct EdgeSystem;
+impl<'a> System<'a> for EdgeSystem {
+    type SystemData = EdgeSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let (width, height) = system_data.terrain.size();
+        let (width, height) = (width as f64, height as f64);
+
+        for (entity, _, vel, pos) in (
+            &*system_data.entities,
+            &system_data.proj,
+            &mut system_data.vel,
+            &mut system_data.pos,
+        )
+            .join()
+        {
+            if pos.0.y >= height {
+                let _ = system_data.entities.delete(entity);
+                continue;
+            }
+
+            match *system_data.edge {
+                EdgeBehavior::Walls => {
+                    if pos.0.x < 0.0 {
+                        pos.0.x = 0.0;
+                        vel.x = vel.x.abs();
+                    } else if pos.0.x >= width {
+                        pos.0.x = width - 1.0;
+                        vel.x = -vel.x.abs();
+                    }
+                }
+                EdgeBehavior::FallOff => (),
+                EdgeBehavior::Wrap => {
+                    if pos.0.x < 0.0 || pos.0.x >= width {
+                        pos.0.x = pos.0.x.rem_euclid(width);
+
+                        // Don't draw a line through the whole level to the old positions
+                        if let Some(trail) = system_data.trail.get_mut(entity) {
+                            trail.clear();
+                        }
+                    }
+                }
+            }
+        }
+
+        for (entity, walk, pos) in (
+            &*system_data.entities,
+            &system_data.walk,
+            &mut system_data.pos,
+        )
+            .join()
+        {
+            if pos.0.y >= height {
+                let _ = system_data.entities.delete(entity);
+
+                let is_ally = system_data.ally.get(entity).is_some();
+                // A unit that fell out of the level has no health left
+                system_data.stats.register_damage(is_ally, 0.0, 0.0);
+                system_data.events.unit_died(is_ally, pos.0);
+                continue;
+            }
+
+            if *system_data.edge == EdgeBehavior::Walls {
+                let min_x = -walk.bounds.min.x;
+                let max_x = width - walk.bounds.max.x;
+                pos.0.x = pos.0.x.clamp(min_x, max_x);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::Enemy;
+    use crate::geom::{BoundingBox, Point};
+
+    fn world(edge: EdgeBehavior) -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+        world.insert(edge);
+        world.insert(Terrain::new((100, 50)));
+        world.insert(Statistics::default());
+        world.insert(EventLog::default());
+
+        world
+    }
+
+    fn projectile(world: &mut World, pos: Point, vel: Velocity) -> Entity {
+        let mut trail = Trail::new(4, 0.1, 0);
+        trail.update(pos.as_i32(), 0.0);
+
+        world
+            .create_entity()
+            .with(Projectile)
+            .with(WorldPosition(pos))
+            .with(vel)
+            .with(trail)
+            .build()
+    }
+
+    fn unit(world: &mut World, pos: Point) -> Entity {
+        world
+            .create_entity()
+            .with(Enemy)
+            .with(WorldPosition(pos))
+            .with(Walk::new(
+                BoundingBox::new(Point::new(1.0, 0.0), Point::new(4.0, 10.0)),
+                10.0,
+            ))
+            .build()
+    }
+
+    fn run(world: &mut World) {
+        EdgeSystem.run_now(world);
+        world.maintain();
+    }
+
+    fn x(world: &World, entity: Entity) -> f64 {
+        world
+            .read_storage::<WorldPosition>()
+            .get(entity)
+            .unwrap()
+            .0
+            .x
+    }
+
+    #[test]
+    fn walls_bounce_projectiles_and_stop_units() {
+        let mut world = world(EdgeBehavior::Walls);
+        let left = projectile(&mut world, Point::new(-3.0, 10.0), Velocity::new(-5.0, 1.0));
+        let right = projectile(&mut world, Point::new(104.0, 10.0), Velocity::new(5.0, 1.0));
+        let walking = unit(&mut world, Point::new(98.0, 10.0));
+        run(&mut world);
+
+        assert_eq!(x(&world, left), 0.0);
+        assert_eq!(x(&world, right), 99.0);
+        let vel = world.read_storage::<Velocity>();
+        assert_eq!(vel.get(left).unwrap().x, 5.0);
+        assert_eq!(vel.get(right).unwrap().x, -5.0);
+        // The right side of the walking bounding box touches the wall
+        assert_eq!(x(&world, walking), 96.0);
+    }
+
+    #[test]
+    fn falling_off_leaves_the_sides_open() {
+        let mut world = world(EdgeBehavior::FallOff);
+        let outside = projectile(&mut world, Point::new(-3.0, 10.0), Velocity::new(-5.0, 1.0));
+        let walking = unit(&mut world, Point::new(120.0, 10.0));
+        let fallen = unit(&mut world, Point::new(120.0, 50.0));
+        run(&mut world);
+
+        assert_eq!(x(&world, outside), -3.0);
+        assert_eq!(x(&world, walking), 120.0);
+        // Units falling below the level die
+        assert!(!world.is_alive(fallen));
+        assert_eq!(world.read_resource::<Statistics>().enemies_killed, 1);
+    }
+
+    #[test]
+    fn wrapping_moves_projectiles_to_the_other_side() {
+        let mut world = world(EdgeBehavior::Wrap);
+        let left = projectile(&mut world, Point::new(-3.0, 10.0), Velocity::new(-5.0, 1.0));
+        let inside = projectile(&mut world, Point::new(50.0, 10.0), Velocity::new(5.0, 1.0));
+        let below = projectile(&mut world, Point::new(50.0, 60.0), Velocity::new(5.0, 1.0));
+        run(&mut world);
+
+        assert_eq!(x(&world, left), 97.0);
+        assert_eq!(x(&world, inside), 50.0);
+        assert!(!world.is_alive(below));
+
+        // The trail doesn't cross the whole level
+        let trail = world.read_storage::<Trail>();
+        assert_eq!(trail.get(left).unwrap().points().count(), 0);
+        assert_eq!(trail.get(inside).unwrap().points().count(), 1);
+    }
+}
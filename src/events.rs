@@ -0,0 +1,110 @@
+use specs::prelude::*;
+use std::collections::VecDeque;
+
+use super::*;
+
+// Amount of events remembered before the oldest ones are removed
+const MAX_EVENTS: usize = 100;
+
+// Seconds an event stays visible in the short overview
+const EVENT_VISIBLE_TIME: f64 = 10.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventCategory {
+    Recruit,
+    Death,
+    Rout,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// Seconds since the battle started.
+    pub time: f64,
+    pub category: EventCategory,
+    pub text: String,
+}
+
+impl Event {
+    /// The event as a line of text prefixed with the time it happened.
+    pub fn line(&self) -> String {
+        let seconds = self.time as u64;
+        let category = match self.category {
+            EventCategory::Recruit => "recruit",
+            EventCategory::Death => "death",
+            EventCategory::Rout => "rout",
+            EventCategory::Warning => "warning",
+        };
+
+        format!(
+            "{:02}:{:02} {:<8}{}",
+            seconds / 60,
+            seconds % 60,
+            category,
+            self.text
+        )
+    }
+}
+
+/// The most recent things that happened during the battle.
+#[derive(Default)]
+pub struct EventLog {
+    events: VecDeque<Event>,
+    time: f64,
+    // Where units died since the deaths were taken last, with whether it was an ally
+    deaths: Vec<(Point, bool)>,
+}
+
+impl EventLog {
+    pub fn push<S: Into<String>>(&mut self, category: EventCategory, text: S) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(Event {
+            time: self.time,
+            category,
+            text: text.into(),
+        });
+    }
+
+    /// Log that a unit died, the side is needed because the entity is already removed.
+    pub fn unit_died(&mut self, is_ally: bool, pos: Point) {
+        self.deaths.push((pos, is_ally));
+
+        if is_ally {
+            self.push(EventCategory::Death, "ally unit died");
+        } else {
+            self.push(EventCategory::Death, "enemy unit killed");
+        }
+    }
+
+    /// The positions of the units that died since the last call and whether they were allies.
+    pub fn take_deaths(&mut self) -> Vec<(Point, bool)> {
+        std::mem::take(&mut self.deaths)
+    }
+
+    /// The last amount of events from oldest to newest.
+    pub fn last(&self, amount: usize) -> impl Iterator<Item = &Event> {
+        self.events
+            .iter()
+            .skip(self.events.len().saturating_sub(amount))
+    }
+
+    /// The last amount of events that happened recently, from oldest to newest.
+    pub fn recent(&self, amount: usize) -> impl Iterator<Item = &Event> {
+        let time = self.time;
+
+        self.last(amount)
+            .filter(move |event| time - event.time < EVENT_VISIBLE_TIME)
+    }
+}
+
+pub struct EventLogSystem;
+impl<'a> System<'a> for EventLogSystem {
+    type SystemData = (Read<'a, DeltaTime>, Write<'a, EventLog>);
+
+    fn run(&mut self, (dt, mut events): Self::SystemData) {
+        events.time += dt.to_seconds();
+    }
+}
@@ -0,0 +1,328 @@
+use crate::audio::Audio;
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+/// How the damage of an explosion decreases with the distance to the center.
+// The built-in projectiles all use the linear falloff
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Falloff {
+    /// Full damage everywhere inside the radius.
+    Constant,
+    /// Full damage at the center, nothing at the edge of the radius.
+    Linear,
+    /// Full damage at the center, dropping to about half at a quarter of the radius and nothing
+    /// at the edge.
+    InverseSquare,
+}
+
+impl Falloff {
+    /// The fraction of the damage between 0.0 and 1.0 that is dealt at the distance.
+    pub fn factor(self, distance: f64, radius: f64) -> f64 {
+        if distance > radius {
+            return 0.0;
+        }
+
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - distance / radius,
+            Falloff::InverseSquare => {
+                // Scaled so a quarter of the radius away roughly halves the damage, the value at
+                // the edge is subtracted so there is no jump to zero outside the radius
+                let inverse_square = |distance: f64| {
+                    let scaled = distance / radius * 4.0;
+                    1.0 / (1.0 + scaled * scaled)
+                };
+                let edge = inverse_square(radius);
+
+                ((inverse_square(distance) - edge) / (1.0 - edge)).max(0.0)
+            }
+        }
+    }
+}
+
+/// Projectiles with this component damage all units around where they hit the terrain.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Explosion {
+    pub radius: f64,
+    pub damage: f64,
+    pub falloff: Falloff,
+    /// Whether units behind terrain are shielded from the blast.
+    pub line_of_sight: bool,
+}
+
+impl Explosion {
+    pub fn new(radius: f64, damage: f64, falloff: Falloff) -> Self {
+        Explosion {
+            radius,
+            damage,
+            falloff,
+            line_of_sight: true,
+        }
+    }
+}
+
+/// An explosion that will damage the units around it in the next frame.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct AreaDamage {
+    pub center: Point,
+    pub explosion: Explosion,
+    pub ignore: Option<IgnoreCollision>,
+    /// Applied to every unit that's hit.
+    pub effect: Option<StatusEffect>,
+}
+
+/// The units affected by an area damage and how much damage each of them got.
+#[derive(Debug, Default)]
+pub struct AreaDamageSummary {
+    pub hits: Vec<(Entity, f64)>,
+    pub total_damage: f64,
+    pub killed: usize,
+}
+
+#[derive(SystemData)]
+pub struct AreaDamageSystemData<'a> {
+    entities: Entities<'a>,
+    terrain: Read<'a, Terrain>,
+    audio: Read<'a, Audio>,
+    updater: Read<'a, LazyUpdate>,
+    area: ReadStorage<'a, AreaDamage>,
+    pos: ReadStorage<'a, WorldPosition>,
+    bb: ReadStorage<'a, BoundingBox>,
+    ally: ReadStorage<'a, Ally>,
+    enemy: ReadStorage<'a, Enemy>,
+    resistance: ReadStorage<'a, Resistance>,
+    effects: WriteStorage<'a, StatusEffects>,
+    health: WriteStorage<'a, Health>,
+    garrison: Write<'a, Garrison>,
+    stats: Write<'a, Statistics>,
+    events: Write<'a, EventLog>,
+}
+
+/// Damage all units with a bounding box inside the radius of the area damage.
+///
+/// The distance is measured to the closest point of the bounding box of the unit, so big units
+/// are hit as soon as their edge is in the radius.
+pub fn apply_area_damage(
+    system_data: &mut AreaDamageSystemData,
+    area: &AreaDamage,
+) -> AreaDamageSummary {
+    let mut summary = AreaDamageSummary::default();
+    let explosion = area.explosion;
+
+    for (target, target_pos, target_bb, target_health) in (
+        &*system_data.entities,
+        &system_data.pos,
+        &system_data.bb,
+        &mut system_data.health,
+    )
+        .join()
+    {
+        // A unit killed earlier this frame is only removed at the end of it, don't count it twice
+        if target_health.0 <= 0.0 {
+            continue;
+        }
+
+        let is_ally = system_data.ally.get(target).is_some();
+        let is_enemy = system_data.enemy.get(target).is_some();
+        match area.ignore {
+            Some(IgnoreCollision::Ally) if is_ally => continue,
+            Some(IgnoreCollision::Enemy) if is_enemy => continue,
+            _ => (),
+        }
+        if matches!(system_data.effects.get(target), Some(effects) if effects.is_invulnerable()) {
+            continue;
+        }
+
+        let target_aabb = *target_bb + *target_pos.0;
+        let closest = Point::new(
+            area.center.x.max(target_aabb.min.x).min(target_aabb.max.x),
+            area.center.y.max(target_aabb.min.y).min(target_aabb.max.y),
+        );
+        let dmg = resisted_damage(
+            system_data.resistance.get(target),
+            Some(DamageType::Blast),
+            explosion.damage
+                * explosion
+                    .falloff
+                    .factor(area.center.distance(*closest), explosion.radius),
+        );
+        if dmg <= 0.0 {
+            continue;
+        }
+
+        // Units behind a wall of terrain don't get hit, the center of the unit is used because
+        // the feet are often touching the ground
+        let target_center = Point::new(
+            (target_aabb.min.x + target_aabb.max.x) / 2.0,
+            (target_aabb.min.y + target_aabb.max.y) / 2.0,
+        );
+        if explosion.line_of_sight
+            && system_data
+                .terrain
+                .line_collides(area.center.as_i32(), target_center.as_i32())
+                .is_some()
+        {
+            continue;
+        }
+
+        let died = reduce_unit_health(
+            &system_data.entities,
+            &mut system_data.stats,
+            target,
+            is_ally,
+            target_health,
+            dmg,
+        );
+        if died {
+            system_data.events.unit_died(is_ally, target_pos.0);
+            spawn_death_effects(&system_data.entities, &system_data.updater, *target_pos);
+            summary.killed += 1;
+        } else if let (Some(effect), Some(effects)) =
+            (area.effect, system_data.effects.get_mut(target))
+        {
+            effects.apply(effect);
+        }
+
+        summary.hits.push((target, dmg));
+        summary.total_damage += dmg;
+    }
+
+    summary
+}
+
+pub struct AreaDamageSystem;
+impl<'a> System<'a> for AreaDamageSystem {
+    type SystemData = AreaDamageSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let areas: Vec<(Entity, AreaDamage)> = (&*system_data.entities, &system_data.area)
+            .join()
+            .map(|(entity, area)| (entity, *area))
+            .collect();
+
+        for (entity, area) in areas {
+            let summary = apply_area_damage(&mut system_data, &area);
+
+            // The archers inside the castle are only hit by a part of the blast
+            for (window, dmg, health_left) in system_data.garrison.take_blast(&area) {
+                system_data.stats.register_damage(true, dmg, health_left);
+                if health_left <= 0.0 {
+                    system_data.events.unit_died(true, window);
+                }
+            }
+
+            if !summary.hits.is_empty() {
+                // Play a sound
+                system_data.audio.play_unit_hit();
+            }
+
+            let _ = system_data.entities.delete(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falloff_is_full_at_the_center_and_zero_at_the_edge() {
+        for falloff in [Falloff::Linear, Falloff::InverseSquare].iter() {
+            assert_eq!(falloff.factor(0.0, 20.0), 1.0);
+            assert_eq!(falloff.factor(20.0, 20.0), 0.0);
+            assert_eq!(falloff.factor(21.0, 20.0), 0.0);
+        }
+        assert_eq!(Falloff::Constant.factor(20.0, 20.0), 1.0);
+    }
+
+    #[test]
+    fn inverse_square_drops_faster_than_linear() {
+        let quarter = Falloff::InverseSquare.factor(5.0, 20.0);
+        assert!(quarter > 0.4 && quarter < 0.5);
+        assert!(quarter < Falloff::Linear.factor(5.0, 20.0));
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+        world.insert(Terrain::new((100, 50)));
+        world.insert(Audio::default());
+        world.insert(Garrison::default());
+        world.insert(Statistics::default());
+        world.insert(EventLog::default());
+
+        world
+    }
+
+    // A unit of 2 by 2 pixels with the center at the position
+    fn unit(world: &mut World, x: f64) -> Entity {
+        world
+            .create_entity()
+            .with(Enemy)
+            .with(WorldPosition(Point::new(x - 1.0, 19.0)))
+            .with(BoundingBox::new(Point::new(0.0, 0.0), Point::new(2.0, 2.0)))
+            .with(Health(100.0))
+            .build()
+    }
+
+    fn explode(world: &mut World, explosion: Explosion) {
+        world
+            .create_entity()
+            .with(AreaDamage {
+                center: Point::new(50.0, 20.0),
+                explosion,
+                ignore: None,
+                effect: None,
+            })
+            .build();
+        AreaDamageSystem.run_now(world);
+        world.maintain();
+    }
+
+    fn health(world: &World, unit: Entity) -> f64 {
+        world.read_storage::<Health>().get(unit).unwrap().0
+    }
+
+    #[test]
+    fn units_outside_the_radius_are_not_damaged() {
+        let mut world = world();
+        let inside = unit(&mut world, 60.0);
+        // The closest edge of the bounding box is just outside of the radius
+        let outside = unit(&mut world, 71.5);
+
+        explode(&mut world, Explosion::new(20.0, 10.0, Falloff::Constant));
+        assert_eq!(health(&world, inside), 90.0);
+        assert_eq!(health(&world, outside), 100.0);
+        assert_eq!(world.read_resource::<Statistics>().damage_dealt, 10.0);
+    }
+
+    #[test]
+    fn terrain_shields_units_from_the_blast() {
+        let mut world = world();
+        let shielded = unit(&mut world, 40.0);
+        let exposed = unit(&mut world, 60.0);
+        {
+            let mut terrain = world.write_resource::<Terrain>();
+            for y in 10..30 {
+                terrain.draw_pixel((45, y), 0xFF_80_60_40);
+            }
+        }
+
+        explode(&mut world, Explosion::new(20.0, 10.0, Falloff::Constant));
+        assert_eq!(health(&world, shielded), 100.0);
+        assert_eq!(health(&world, exposed), 90.0);
+
+        // Without the line of sight check the wall doesn't help
+        let explosion = Explosion {
+            line_of_sight: false,
+            ..Explosion::new(20.0, 10.0, Falloff::Constant)
+        };
+        explode(&mut world, explosion);
+        assert_eq!(health(&world, shielded), 90.0);
+        assert_eq!(health(&world, exposed), 80.0);
+    }
+}
@@ -0,0 +1,344 @@
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// Fraction of the damage of an explosion that reaches the units behind the castle walls
+const BLAST_SHARE: f64 = 0.25;
+
+/// Marks the projectiles shot from the castle windows so their kills can be counted.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct GarrisonShot;
+
+/// A castle window, it has a turret while an archer is stationed behind it.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct GarrisonWindow(pub usize);
+
+/// An archer inside the castle, it's removed from the field and keeps its health and veterancy.
+#[derive(Debug, Copy, Clone)]
+pub struct GarrisonedUnit {
+    pub health: f64,
+    pub veterancy: Option<Veterancy>,
+}
+
+/// The archers inside the allied castle, every window holds a single archer.
+#[derive(Debug, Default)]
+pub struct Garrison {
+    pub units: Vec<GarrisonedUnit>,
+    /// Archers closer than this to the left edge of the level are standing at the castle door.
+    pub door_width: f64,
+
+    windows: Vec<Point>,
+}
+
+impl Garrison {
+    pub fn new(windows: Vec<Point>, door_width: f64) -> Self {
+        Garrison {
+            units: Vec::new(),
+            door_width,
+
+            windows,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.units.len() >= self.capacity()
+    }
+
+    /// Damage the garrisoned units with an explosion and remove the ones that died.
+    ///
+    /// Every unit takes a share of the damage at the window it's stationed behind, the position,
+    /// damage and health left are returned for every unit that got hit.
+    pub fn take_blast(&mut self, area: &AreaDamage) -> Vec<(Point, f64, f64)> {
+        if area.ignore == Some(IgnoreCollision::Ally) {
+            return Vec::new();
+        }
+
+        let explosion = area.explosion;
+        let mut hits = Vec::new();
+        for (unit, window) in self.units.iter_mut().zip(self.windows.iter()) {
+            let dmg = explosion.damage
+                * explosion
+                    .falloff
+                    .factor(area.center.distance(**window), explosion.radius)
+                * BLAST_SHARE;
+            if dmg <= 0.0 {
+                continue;
+            }
+
+            unit.health -= dmg;
+            hits.push((*window, dmg, unit.health));
+        }
+        self.units.retain(|unit| unit.health > 0.0);
+
+        hits
+    }
+}
+
+/// Move the allied archers standing at the castle door into the castle until it's full.
+///
+/// Returns the amount of archers that entered the castle.
+pub fn garrison_archers(world: &mut World) -> usize {
+    let entities = world.entities();
+    let mut garrison = world.write_resource::<Garrison>();
+
+    let door_width = garrison.door_width;
+    let archers: Vec<(Entity, GarrisonedUnit)> = (
+        &entities,
+        &world.read_storage::<Ally>(),
+        &world.read_storage::<Turret>(),
+        &world.read_storage::<WorldPosition>(),
+        &world.read_storage::<Health>(),
+        &world.read_storage::<UnitState>(),
+        world.read_storage::<Veterancy>().maybe(),
+    )
+        .join()
+        .filter(|(_, _, _, pos, _, state, _)| pos.0.x < door_width && **state != UnitState::Flee)
+        .map(|(entity, _, _, _, health, _, veterancy)| {
+            (
+                entity,
+                GarrisonedUnit {
+                    health: health.0,
+                    veterancy: veterancy.copied(),
+                },
+            )
+        })
+        .collect();
+
+    let mut entered = 0;
+    for (archer, unit) in archers {
+        if garrison.is_full() {
+            break;
+        }
+
+        garrison.units.push(unit);
+        let _ = entities.delete(archer);
+        entered += 1;
+    }
+
+    entered
+}
+
+/// Let the archer that entered the castle last out of the door again.
+///
+/// Returns false when the castle is empty or the door is blocked.
+pub fn ungarrison_archer(world: &mut World) -> bool {
+    let unit = match world.read_resource::<Garrison>().units.last() {
+        Some(unit) => *unit,
+        None => return false,
+    };
+    if !spawn_archer(world, unit.health, unit.veterancy) {
+        return false;
+    }
+
+    world.write_resource::<Garrison>().units.pop();
+    true
+}
+
+#[derive(SystemData)]
+pub struct GarrisonSystemData<'a> {
+    entities: Entities<'a>,
+    garrison: Read<'a, Garrison>,
+    window: ReadStorage<'a, GarrisonWindow>,
+    turret: WriteStorage<'a, Turret>,
+}
+
+pub struct GarrisonSystem;
+impl<'a> System<'a> for GarrisonSystem {
+    type SystemData = GarrisonSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        for (e, window) in (&*system_data.entities, &system_data.window).join() {
+            let manned = window.0 < system_data.garrison.units.len();
+            if manned && system_data.turret.get(e).is_none() {
+                // Shooting from high up in the castle reaches further than from the field
+                let _ = system_data.turret.insert(
+                    e,
+                    Turret {
+                        delay: 3.0,
+                        min_distance: 20.0,
+                        max_strength: 200.0,
+                        flight_time: 2.5,
+                        strength_variation: 0.1,
+                        line_of_sight: true,
+                        ..Turret::default()
+                    },
+                );
+            } else if !manned {
+                system_data.turret.remove(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn world(windows: usize) -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+
+        let mut images = HashMap::new();
+        images.insert("ally-archer1".to_string(), 0);
+        world.insert(Images(images));
+        world.insert(Terrain::new((100, 400)));
+        world.insert(EventLog::default());
+        world.insert(Garrison::new(
+            (0..windows)
+                .map(|i| Point::new(10.0 + i as f64 * 10.0, 300.0))
+                .collect(),
+            20.0,
+        ));
+
+        world
+    }
+
+    fn archer(world: &mut World, x: f64, state: UnitState) -> Entity {
+        world
+            .create_entity()
+            .with(Ally)
+            .with(Turret::default())
+            .with(WorldPosition(Point::new(x, 100.0)))
+            .with(Health(20.0))
+            .with(state)
+            .build()
+    }
+
+    fn blast(center: Point, damage: f64) -> AreaDamage {
+        AreaDamage {
+            center,
+            explosion: Explosion::new(40.0, damage, Falloff::Linear),
+            ignore: None,
+            effect: None,
+        }
+    }
+
+    #[test]
+    fn only_archers_at_the_door_enter_until_it_is_full() {
+        let mut world = world(2);
+        let away = archer(&mut world, 50.0, UnitState::Walk);
+        let fleeing = archer(&mut world, 5.0, UnitState::Flee);
+        for _ in 0..3 {
+            archer(&mut world, 5.0, UnitState::Walk);
+        }
+
+        assert_eq!(garrison_archers(&mut world), 2);
+        world.maintain();
+
+        assert!(world.read_resource::<Garrison>().is_full());
+        assert_eq!(world.read_storage::<Turret>().join().count(), 3);
+        assert!(world.is_alive(away));
+        assert!(world.is_alive(fleeing));
+
+        // Nobody fits anymore
+        assert_eq!(garrison_archers(&mut world), 0);
+    }
+
+    #[test]
+    fn blast_damage_is_shared_by_distance_to_the_windows() {
+        let mut garrison = Garrison::new(vec![Point::new(0.0, 0.0), Point::new(20.0, 0.0)], 20.0);
+        garrison.units = vec![
+            GarrisonedUnit {
+                health: 10.0,
+                veterancy: None,
+            };
+            2
+        ];
+
+        let hits: Vec<(f64, f64, f64)> = garrison
+            .take_blast(&blast(Point::new(0.0, 0.0), 20.0))
+            .iter()
+            .map(|(window, dmg, health)| (window.x, *dmg, *health))
+            .collect();
+        // A quarter of the damage, the second window gets half of that
+        assert_eq!(hits, vec![(0.0, 5.0, 5.0), (20.0, 2.5, 7.5)]);
+
+        // Blasts out of range don't hit anything
+        assert!(garrison
+            .take_blast(&blast(Point::new(100.0, 0.0), 20.0))
+            .is_empty());
+
+        // Explosions of allied projectiles don't hurt the garrison
+        let mut own = blast(Point::new(0.0, 0.0), 100.0);
+        own.ignore = Some(IgnoreCollision::Ally);
+        assert!(garrison.take_blast(&own).is_empty());
+
+        // Only the unit behind the closest window dies
+        garrison.take_blast(&blast(Point::new(0.0, 0.0), 24.0));
+        assert_eq!(garrison.units.len(), 1);
+        assert!((garrison.units[0].health - 4.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ungarrisoned_archer_keeps_its_health() {
+        let mut world = world(2);
+        world.write_resource::<Garrison>().units = vec![
+            GarrisonedUnit {
+                health: 20.0,
+                veterancy: None,
+            },
+            GarrisonedUnit {
+                health: 7.5,
+                veterancy: None,
+            },
+        ];
+
+        assert!(ungarrison_archer(&mut world));
+        world.maintain();
+        let health: Vec<f64> = (
+            &world.read_storage::<Ally>(),
+            &world.read_storage::<Health>(),
+        )
+            .join()
+            .map(|(_, health)| health.0)
+            .collect();
+        assert_eq!(health, vec![7.5]);
+        assert_eq!(world.read_resource::<Garrison>().units.len(), 1);
+
+        // The archer that just left blocks the door
+        assert!(!ungarrison_archer(&mut world));
+        assert_eq!(world.read_resource::<Garrison>().units.len(), 1);
+    }
+
+    #[test]
+    fn archer_keeps_its_veterancy_inside_the_castle() {
+        let mut world = world(1);
+        let archer = archer(&mut world, 5.0, UnitState::Walk);
+        let mut veterancy = Veterancy::new(UnitStats {
+            max_health: 20.0,
+            melee_damage: 5.0,
+            projectile_damage: 5.0,
+            walk_speed: 20.0,
+        });
+        for _ in 0..5 {
+            veterancy.register_kill();
+        }
+        world
+            .write_storage::<Veterancy>()
+            .insert(archer, veterancy)
+            .unwrap();
+
+        assert_eq!(garrison_archers(&mut world), 1);
+        world.maintain();
+        assert!(ungarrison_archer(&mut world));
+        world.maintain();
+
+        let veterancy = world.read_storage::<Veterancy>();
+        let health_bar = world.read_storage::<HealthBar>();
+        let damage = world.read_storage::<Damage>();
+        let (veterancy, health_bar, damage) =
+            (&veterancy, &health_bar, &damage).join().next().unwrap();
+        assert_eq!(veterancy.kills, 5);
+        assert_eq!(veterancy.level(), 2);
+        // The stats of the level are applied to the archer that left
+        assert!((health_bar.max_health - 26.0).abs() < 1e-9);
+        assert!((damage.0 - 6.5).abs() < 1e-9);
+    }
+}
@@ -16,6 +16,25 @@ pub struct FloatingText {
     pub text: String,
     pub pos: Point,
     pub time_alive: f64,
+
+    start: Point,
+    elapsed: f64,
+    rise: Tween,
+}
+
+impl FloatingText {
+    pub fn new(text: String, pos: Point, time_alive: f64) -> Self {
+        FloatingText {
+            text,
+            pos,
+            time_alive,
+
+            start: pos,
+            elapsed: 0.0,
+            // Float up fast at first and slow down until the text disappears
+            rise: Tween::new(0.0, time_alive * 20.0, time_alive, Easing::QuadOut),
+        }
+    }
 }
 
 pub struct FloatingTextSystem;
@@ -38,7 +57,8 @@ impl<'a> System<'a> for FloatingTextSystem {
             }
 
             // Float the text up
-            text.pos.0.y -= dt * 20.0;
+            text.elapsed += dt;
+            text.pos.0.y = text.start.y - text.rise.value_at(text.elapsed);
         }
     }
 }
@@ -100,6 +120,13 @@ impl IngameGui {
         self.cs.mouse_down = left_is_down;
     }
 
+    /// Release the buttons without clicking them.
+    pub fn cancel_press(&mut self) {
+        self.cs.mouse_pos = (-1, -1);
+        self.cs.mouse_down = false;
+        self.gui.update(&self.cs);
+    }
+
     pub fn update(&mut self) -> GuiEvent {
         let mut result = GuiEvent::None;
 
@@ -123,13 +150,33 @@ impl IngameGui {
         result
     }
 
-    pub fn draw_label(&mut self, buffer: &mut Vec<u32>, text: &str, pos: (i32, i32)) {
+    /// Whether the mouse is above the bar with the recruit buttons.
+    pub fn recruit_hovered(&self) -> bool {
+        let (x, y) = self.cs.mouse_pos;
+        let size = self.menu_bg.size();
+
+        x >= self.bg_pos.0
+            && x < self.bg_pos.0 + size.0
+            && y >= self.bg_pos.1
+            && y < self.bg_pos.1 + size.1
+    }
+
+    pub fn draw_label(&mut self, buffer: &mut [u32], text: &str, pos: (i32, i32)) {
         let default_font = self.gui.default_font();
         self.gui
             .draw_label(buffer, default_font, &text.to_string(), pos);
     }
 
-    pub fn render(&mut self, buffer: &mut Vec<u32>) {
+    pub fn draw_garrison(&mut self, buffer: &mut [u32], garrisoned: usize, capacity: usize) {
+        let pos = (self.bg_pos.0 + 72, self.bg_pos.1 + 16);
+        self.draw_label(
+            buffer,
+            &format!("garrison {}/{}", garrisoned, capacity),
+            pos,
+        );
+    }
+
+    pub fn render(&mut self, buffer: &mut [u32]) {
         self.menu_bg.blit(buffer, self.size.0 as usize, self.bg_pos);
 
         self.gui.draw_to_buffer(buffer);
@@ -0,0 +1,295 @@
+use minifb::{MouseButton, MouseMode, Window};
+
+// Maximum amount of pixels the mouse can move between pressing and releasing for a click
+const CLICK_DISTANCE: i32 = 4;
+// Maximum seconds between pressing and releasing for a click
+const CLICK_TIME: f64 = 0.3;
+// Maximum seconds between two clicks for a double click
+const DOUBLE_CLICK_TIME: f64 = 0.4;
+
+const BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Middle, MouseButton::Right];
+
+/// What happened with the mouse this frame, derived from the raw button states.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MouseEvent {
+    /// The button was pressed and released at the same spot.
+    Click(MouseButton, (i32, i32)),
+    /// The second click shortly after another click, it follows the event of the click itself.
+    DoubleClick(MouseButton, (i32, i32)),
+    /// The mouse moved too far with the button held to be a click, with the start position.
+    DragStart(MouseButton, (i32, i32)),
+    /// The mouse moved while dragging, with the start and current position.
+    DragMove(MouseButton, (i32, i32), (i32, i32)),
+    /// The button was released after dragging, with the start and end position.
+    DragEnd(MouseButton, (i32, i32), (i32, i32)),
+    /// The button was released outside of the window or the window lost focus while it was held.
+    Cancel(MouseButton),
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Press {
+    start: (i32, i32),
+    time: f64,
+    dragging: bool,
+}
+
+/// Turns the mouse state of every frame into clicks and drags.
+#[derive(Debug)]
+pub struct Mouse {
+    pub click_distance: i32,
+    pub click_time: f64,
+    pub double_click_time: f64,
+
+    pos: Option<(i32, i32)>,
+    // The held buttons in the same order as `BUTTONS`
+    presses: [Option<Press>; 3],
+    // Seconds since the last click of every button
+    since_click: [Option<f64>; 3],
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Mouse {
+            click_distance: CLICK_DISTANCE,
+            click_time: CLICK_TIME,
+            double_click_time: DOUBLE_CLICK_TIME,
+
+            pos: None,
+            presses: [None; 3],
+            since_click: [None; 3],
+        }
+    }
+}
+
+impl Mouse {
+    /// Read the mouse state of the window and return the events that happened since last frame.
+    pub fn update(&mut self, window: &mut Window, dt: f64) -> Vec<MouseEvent> {
+        let active = window.is_active();
+        let pos = window
+            .get_mouse_pos(MouseMode::Discard)
+            .map(|pos| (pos.0 as i32, pos.1 as i32));
+        let mut down = [false; 3];
+        for (down, button) in down.iter_mut().zip(BUTTONS.iter()) {
+            *down = window.get_mouse_down(*button);
+        }
+
+        self.update_from(pos, down, active, dt)
+    }
+
+    /// Return the events from the mouse state of a frame, with the buttons in the order of left,
+    /// middle and right.
+    pub fn update_from(
+        &mut self,
+        pos: Option<(i32, i32)>,
+        down: [bool; 3],
+        active: bool,
+        dt: f64,
+    ) -> Vec<MouseEvent> {
+        let mut events = Vec::new();
+        self.pos = pos;
+
+        for (i, button) in BUTTONS.iter().enumerate() {
+            if let Some(since_click) = &mut self.since_click[i] {
+                *since_click += dt;
+            }
+
+            let (mut press, pos) = match (self.presses[i], pos) {
+                (Some(mut press), Some(pos)) if active => {
+                    press.time += dt;
+                    (press, pos)
+                }
+                (Some(_), _) => {
+                    // Don't let the button get stuck when the release can't be seen
+                    self.presses[i] = None;
+                    events.push(MouseEvent::Cancel(*button));
+                    continue;
+                }
+                (None, Some(pos)) if active && down[i] => {
+                    self.presses[i] = Some(Press {
+                        start: pos,
+                        time: 0.0,
+                        dragging: false,
+                    });
+                    continue;
+                }
+                (None, _) => continue,
+            };
+            let moved = (pos.0 - press.start.0).abs() > self.click_distance
+                || (pos.1 - press.start.1).abs() > self.click_distance;
+
+            if down[i] {
+                if press.dragging {
+                    events.push(MouseEvent::DragMove(*button, press.start, pos));
+                } else if moved || press.time > self.click_time {
+                    press.dragging = true;
+                    events.push(MouseEvent::DragStart(*button, press.start));
+                }
+                self.presses[i] = Some(press);
+            } else {
+                self.presses[i] = None;
+
+                if press.dragging {
+                    events.push(MouseEvent::DragEnd(*button, press.start, pos));
+                } else {
+                    events.push(MouseEvent::Click(*button, pos));
+
+                    match self.since_click[i] {
+                        Some(since_click) if since_click < self.double_click_time => {
+                            events.push(MouseEvent::DoubleClick(*button, pos));
+                            // A third click starts a new double click
+                            self.since_click[i] = None;
+                        }
+                        _ => self.since_click[i] = Some(0.0),
+                    }
+                }
+            }
+        }
+
+        events
+    }
+
+    /// The position of the mouse, `None` when it's outside of the window.
+    pub fn pos(&self) -> Option<(i32, i32)> {
+        self.pos
+    }
+
+    /// Whether the button is held down, this is reset when the press is cancelled.
+    pub fn is_down(&self, button: MouseButton) -> bool {
+        self.presses[Self::index(button)].is_some()
+    }
+
+    fn index(button: MouseButton) -> usize {
+        BUTTONS.iter().position(|b| *b == button).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT: [bool; 3] = [true, false, false];
+    const UP: [bool; 3] = [false; 3];
+
+    // Press and release the left button at the position after a short time
+    fn click(mouse: &mut Mouse, pos: (i32, i32)) -> Vec<MouseEvent> {
+        mouse.update_from(Some(pos), LEFT, true, 0.05);
+        mouse.update_from(Some(pos), UP, true, 0.05)
+    }
+
+    #[test]
+    fn small_movements_are_clicks_and_large_ones_drags() {
+        let mut mouse = Mouse::default();
+        mouse.update_from(Some((10, 10)), LEFT, true, 0.05);
+        assert!(mouse
+            .update_from(Some((13, 13)), LEFT, true, 0.05)
+            .is_empty());
+        assert_eq!(
+            mouse.update_from(Some((13, 13)), UP, true, 0.05),
+            vec![MouseEvent::Click(MouseButton::Left, (13, 13))]
+        );
+
+        mouse.update_from(Some((10, 10)), LEFT, true, 0.05);
+        assert_eq!(
+            mouse.update_from(Some((15, 10)), LEFT, true, 0.05),
+            vec![MouseEvent::DragStart(MouseButton::Left, (10, 10))]
+        );
+        assert_eq!(
+            mouse.update_from(Some((20, 10)), LEFT, true, 0.05),
+            vec![MouseEvent::DragMove(MouseButton::Left, (10, 10), (20, 10))]
+        );
+        assert_eq!(
+            mouse.update_from(Some((20, 10)), UP, true, 0.05),
+            vec![MouseEvent::DragEnd(MouseButton::Left, (10, 10), (20, 10))]
+        );
+    }
+
+    #[test]
+    fn holding_too_long_is_a_drag() {
+        let mut mouse = Mouse::default();
+        mouse.update_from(Some((10, 10)), LEFT, true, 0.05);
+        assert!(mouse
+            .update_from(Some((10, 10)), LEFT, true, 0.2)
+            .is_empty());
+        assert_eq!(
+            mouse.update_from(Some((10, 10)), LEFT, true, 0.2),
+            vec![MouseEvent::DragStart(MouseButton::Left, (10, 10))]
+        );
+    }
+
+    #[test]
+    fn third_click_starts_a_new_double_click() {
+        let mut mouse = Mouse::default();
+        let pos = (10, 10);
+
+        assert_eq!(
+            click(&mut mouse, pos),
+            vec![MouseEvent::Click(MouseButton::Left, pos)]
+        );
+        assert_eq!(
+            click(&mut mouse, pos),
+            vec![
+                MouseEvent::Click(MouseButton::Left, pos),
+                MouseEvent::DoubleClick(MouseButton::Left, pos)
+            ]
+        );
+        assert_eq!(
+            click(&mut mouse, pos),
+            vec![MouseEvent::Click(MouseButton::Left, pos)]
+        );
+        assert_eq!(
+            click(&mut mouse, pos),
+            vec![
+                MouseEvent::Click(MouseButton::Left, pos),
+                MouseEvent::DoubleClick(MouseButton::Left, pos)
+            ]
+        );
+
+        // Too slow for a double click
+        mouse.update_from(Some(pos), UP, true, 1.0);
+        assert_eq!(
+            click(&mut mouse, pos),
+            vec![MouseEvent::Click(MouseButton::Left, pos)]
+        );
+        mouse.update_from(Some(pos), UP, true, 1.0);
+        assert_eq!(
+            click(&mut mouse, pos),
+            vec![MouseEvent::Click(MouseButton::Left, pos)]
+        );
+    }
+
+    #[test]
+    fn releasing_outside_the_window_cancels() {
+        let mut mouse = Mouse::default();
+        mouse.update_from(Some((10, 10)), LEFT, true, 0.05);
+        assert!(mouse.is_down(MouseButton::Left));
+
+        assert_eq!(
+            mouse.update_from(None, UP, true, 0.05),
+            vec![MouseEvent::Cancel(MouseButton::Left)]
+        );
+        assert!(!mouse.is_down(MouseButton::Left));
+        assert_eq!(mouse.pos(), None);
+
+        // Coming back into the window doesn't release the button again
+        assert!(mouse.update_from(Some((10, 10)), UP, true, 0.05).is_empty());
+    }
+
+    #[test]
+    fn losing_focus_while_held_cancels() {
+        let mut mouse = Mouse::default();
+        mouse.update_from(Some((10, 10)), [false, false, true], true, 0.05);
+        assert!(mouse.is_down(MouseButton::Right));
+
+        assert_eq!(
+            mouse.update_from(Some((10, 10)), [false, false, true], false, 0.05),
+            vec![MouseEvent::Cancel(MouseButton::Right)]
+        );
+        assert!(!mouse.is_down(MouseButton::Right));
+
+        // Presses aren't registered without focus
+        assert!(mouse
+            .update_from(Some((10, 10)), LEFT, false, 0.05)
+            .is_empty());
+        assert!(!mouse.is_down(MouseButton::Left));
+    }
+}
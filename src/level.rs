@@ -1,56 +1,305 @@
 use blit::Animation;
-use cgmath::Point2;
+use collision::Discrete;
 use specs::*;
+use std::{borrow::Cow, result::Result};
 
 use crate::*;
 
+/// The amount of levels that can be played.
+pub const LEVEL_COUNT: u8 = 1;
+
 const WOOD_COLOR: u32 = 0x66_39_31;
+const ARROW_TRAIL_COLOR: u32 = 0xFF_CB_DB_FC;
+const ROCK_TRAIL_COLOR: u32 = 0xFF_9B_AD_B7;
+
+// Amount of pixels a unit walks between leaving footprints
+const FOOTPRINT_INTERVAL: f64 = 6.0;
+
+// Arrows hitting the ground at a shallow angle bounce off once
+const ARROW_RICOCHET_ANGLE: f64 = 15.0;
+const ARROW_RICOCHET_SPEED: f64 = 80.0;
+const ARROW_RICOCHET_RESTITUTION: f64 = 0.5;
+
+// Soldiers wear armor that stops arrows but doesn't help against heavy blows
+const SOLDIER_RESISTANCE: [(DamageType, f64); 2] =
+    [(DamageType::Pierce, 0.5), (DamageType::Crush, 1.2)];
+
+// Amount of pixels a spawn position can be moved to the surface before a warning is shown
+const MAX_SPAWN_ADJUSTMENT: f64 = 3.0;
+
+/// What happens at the sides of the first level.
+const LEVEL1_EDGE_BEHAVIOR: EdgeBehavior = EdgeBehavior::FallOff;
+
+// Width of the area in front of both castles of the first level where units are protected
+const LEVEL1_PROTECTION_WIDTH: f64 = 60.0;
+
+/// Health of a freshly recruited archer.
+pub const ARCHER_HEALTH: f64 = 20.0;
+
+// Seconds freshly recruited units can't be damaged while they are in front of the castle
+const SPAWN_PROTECTION_TIME: f64 = 5.0;
+
+/// Positions in the allied castle where garrisoned archers shoot from.
+const LEVEL1_GARRISON_WINDOWS: [(f64, f64); 3] = [(10.0, 295.0), (18.0, 305.0), (25.0, 315.0)];
+// Archers closer than this to the left edge of the first level are standing at the castle door
+const LEVEL1_GARRISON_DOOR_WIDTH: f64 = 20.0;
+
+// File with the props of the first level in the levels folder of the mods
+const LEVEL1_PROPS_FILE: &str = "level1.props";
+/// Sprites merged into the terrain of the first level, in the order they are drawn.
+///
+/// Every line has the name of the sprite followed by the horizontal position, mods can replace
+/// it with their own file.
+const LEVEL1_PROPS: &str = "\
+# The rock pile next to the allied castle
+projectile1 310
+projectile1 314
+projectile1 312
+projectile1 560
+# The rocks around the enemy camp
+projectile1 790
+projectile1 794
+projectile1 1000
+";
+
+/// How the units and projectiles are treated at the sides of the level.
+pub fn edge_behavior(level: u8) -> EdgeBehavior {
+    if level == 1 {
+        LEVEL1_EDGE_BEHAVIOR
+    } else {
+        EdgeBehavior::default()
+    }
+}
+
+/// The areas in front of the castles where recruits are protected.
+pub fn protection_zones(level: u8) -> ProtectionZones {
+    if level == 1 {
+        ProtectionZones {
+            ally: ProtectionZone::new(0.0, LEVEL1_PROTECTION_WIDTH),
+            enemy: ProtectionZone::new(WIDTH as f64 - LEVEL1_PROTECTION_WIDTH, WIDTH as f64),
+        }
+    } else {
+        ProtectionZones::default()
+    }
+}
+
+/// The allied castle that archers can be stationed in.
+pub fn garrison(level: u8) -> Garrison {
+    if level == 1 {
+        Garrison::new(
+            LEVEL1_GARRISON_WINDOWS
+                .iter()
+                .map(|(x, y)| Point::new(*x, *y))
+                .collect(),
+            LEVEL1_GARRISON_DOOR_WIDTH,
+        )
+    } else {
+        Garrison::default()
+    }
+}
+
+/// Merge the decoration of the level into the terrain so it can be destroyed.
+///
+/// Every prop is placed on the surface below the top of the level, props placed on top of each
+/// other stack.
+pub fn place_props(world: &mut World, render: &mut Render, level: u8) {
+    if level != 1 {
+        return;
+    }
+
+    let props = {
+        let mods = world.read_resource::<Mods>();
+        let bytes = mods
+            .get(
+                "levels",
+                LEVEL1_PROPS_FILE,
+                Some(Cow::Borrowed(LEVEL1_PROPS.as_bytes())),
+            )
+            .unwrap();
+        // Files from mods are already validated when they are loaded
+        parse_props(&String::from_utf8_lossy(&bytes)).unwrap()
+    };
+
+    let images = &*world.read_resource::<Images>();
+    let mut terrain = world.write_resource::<Terrain>();
+    let mut events = world.write_resource::<EventLog>();
+    for (name, x) in props {
+        let image = match images.0.get(&name) {
+            Some(image) => *image,
+            None => {
+                events.push(
+                    EventCategory::Warning,
+                    format!("prop \"{}\" is not a known sprite", name),
+                );
+                continue;
+            }
+        };
+        let surface = match terrain.surface_below(Point::new(x, 0.0)) {
+            Some(surface) => surface,
+            None => continue,
+        };
+
+        render
+            .draw_sprite_terrain(&mut terrain, image, surface.as_i32())
+            .unwrap();
+    }
+}
+
+/// Read the sprite names and horizontal positions of the props of a level.
+///
+/// Empty lines and lines starting with `#` are skipped.
+pub fn parse_props(text: &str) -> Result<Vec<(String, f64)>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(
+            |(number, line)| match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [name, x] => x
+                    .parse::<f64>()
+                    .map(|x| (name.to_string(), x))
+                    .map_err(|_| format!("line {}: invalid position \"{}\"", number, x)),
+                _ => Err(format!("line {}: expected \"<sprite> <x>\"", number)),
+            },
+        )
+        .collect()
+}
+
+/// Move the spawn position of a unit so it stands on the terrain surface below it.
+///
+/// Returns `None` when another unit is already standing at the spot.
+fn spawn_on_surface(
+    world: &World,
+    name: &str,
+    pos: Point,
+    walk: &Walk,
+    bb: BoundingBox,
+) -> Option<WorldPosition> {
+    let terrain = world.read_resource::<Terrain>();
+    let mut events = world.write_resource::<EventLog>();
+
+    let snapped = match terrain.surface_below(pos) {
+        // Place the bottom of the walking bounding box right on top of the surface
+        Some(surface) => Point::new(pos.x, surface.y - walk.bounds.max.y),
+        // There is no ground to stand on, let the unit fall
+        None => pos,
+    };
+    if (snapped.y - pos.y).abs() > MAX_SPAWN_ADJUSTMENT {
+        events.push(
+            EventCategory::Warning,
+            format!(
+                "spawn of \"{}\" moved {:.0} pixels to stand on the terrain",
+                name,
+                snapped.y - pos.y
+            ),
+        );
+    }
 
-pub fn buy_archer(world: &mut World) {
+    let aabb = bb + snapped;
+    let occupied = (
+        &world.read_storage::<WorldPosition>(),
+        &world.read_storage::<BoundingBox>(),
+        &world.read_storage::<Health>(),
+    )
+        .join()
+        .any(|(other_pos, other_bb, _)| aabb.intersects(&*(*other_bb + *other_pos.0)));
+    if occupied {
+        events.push(
+            EventCategory::Warning,
+            format!("spawn of \"{}\" is blocked by another unit", name),
+        );
+        return None;
+    }
+
+    Some(WorldPosition(snapped))
+}
+
+/// Recruit an archer, returns false when the castle door is blocked.
+pub fn buy_archer(world: &mut World) -> bool {
+    if !spawn_archer(world, ARCHER_HEALTH, None) {
+        return false;
+    }
+
+    world.write_resource::<Statistics>().units_recruited += 1;
+    world
+        .write_resource::<EventLog>()
+        .push(EventCategory::Recruit, "archer recruited");
+
+    true
+}
+
+/// Place an allied archer at the castle door with the health and veterancy it has left.
+///
+/// Returns false when the castle door is blocked by another unit.
+pub fn spawn_archer(world: &mut World, health: f64, veterancy: Option<Veterancy>) -> bool {
     let archer_sprite = {
         let images = &*world.read_resource::<Images>();
 
         *images.0.get("ally-archer1").unwrap()
     };
 
-    let health = 20.0;
+    let veterancy = veterancy.unwrap_or_else(|| {
+        Veterancy::new(UnitStats {
+            max_health: ARCHER_HEALTH,
+            melee_damage: 5.0,
+            projectile_damage: 5.0,
+            walk_speed: 20.0,
+        })
+    });
+    // A veteran leaving the castle keeps the stats of its level
+    let stats = veterancy.apply_modifiers(&veterancy.base);
+
+    let walk = Walk::new(
+        BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
+        stats.walk_speed,
+    );
+    let bb = BoundingBox::new(Point::new(0.0, 0.0), Point::new(5.0, 10.0));
+    let pos = match spawn_on_surface(world, "ally-archer1", Point::new(1.0, 340.0), &walk, bb) {
+        Some(pos) => pos,
+        None => return false,
+    };
 
     world
         .create_entity()
         .with(Ally)
+        .with(StatusEffects::default().with_effect(StatusEffect::timed(
+            StatusEffectKind::Invulnerable,
+            SPAWN_PROTECTION_TIME,
+        )))
         .with(Anim::new(archer_sprite, Animation::start(0, 2, true)))
-        .with(WorldPosition(Point::new(1.0, 340.0)))
-        .with(Walk::new(
-            BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
-            20.0,
-        ))
-        .with(BoundingBox::new(
-            Point::new(0.0, 0.0),
-            Point::new(5.0, 10.0),
-        ))
+        .with(pos)
+        .with(walk)
+        .with(Footprints::new(FOOTPRINT_INTERVAL))
+        .with(bb)
         .with(Destination(1280.0))
-        .with(Health(20.0))
-        .with(HealthBar {
-            health,
-            max_health: health,
-            width: 5,
-            pos: Point2::new(0, 0),
-            offset: (1, -3),
-        })
-        .with(Melee::new(5.0, 1.0))
+        .with(Health(health))
+        .with(HealthBar::new(stats.max_health, 5, (1, -3)))
+        .with(Melee::new(stats.melee_damage, 1.0, DamageType::Pierce))
+        .with(veterancy)
+        .with(Stagger::new(1.0))
+        .with(Morale::new(0.3, 0.7))
         .with(Turret {
             delay: 3.0,
             min_distance: 20.0,
             max_strength: 150.0,
             flight_time: 2.0,
             strength_variation: 0.1,
+            line_of_sight: true,
             ..Turret::default()
         })
         .with(TurretOffset((2.0, 2.0)))
         .with(Point::new(0.0, 0.0))
         .with(Arrow(3.0))
         .with(Line::new(WOOD_COLOR))
-        .with(Damage(5.0))
+        .with(Trail::new(4, 0.05, ARROW_TRAIL_COLOR))
+        .with(Ricochet::new(
+            ARROW_RICOCHET_ANGLE,
+            ARROW_RICOCHET_SPEED,
+            ARROW_RICOCHET_RESTITUTION,
+            1,
+        ))
+        .with(Damage(stats.projectile_damage))
+        .with(DamageType::Pierce)
         .with(ProjectileBoundingBox(BoundingBox::new(
             Point::new(0.0, 0.0),
             Point::new(1.0, 1.0),
@@ -58,9 +307,28 @@ pub fn buy_archer(world: &mut World) {
         .with(IgnoreCollision::Ally)
         .with(UnitState::Walk)
         .build();
+
+    true
+}
+
+/// Recruit a soldier, returns false when the castle door is blocked.
+pub fn buy_soldier(world: &mut World) -> bool {
+    if !spawn_soldier(world) {
+        return false;
+    }
+
+    world.write_resource::<Statistics>().units_recruited += 1;
+    world
+        .write_resource::<EventLog>()
+        .push(EventCategory::Recruit, "soldier recruited");
+
+    true
 }
 
-pub fn buy_soldier(world: &mut World) {
+/// Place a fresh allied soldier at the castle door.
+///
+/// Returns false when the castle door is blocked by another unit.
+pub fn spawn_soldier(world: &mut World) -> bool {
     let soldier_sprite = {
         let images = &*world.read_resource::<Images>();
 
@@ -69,31 +337,154 @@ pub fn buy_soldier(world: &mut World) {
 
     let health = 50.0;
 
+    let walk = Walk::new(
+        BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
+        15.0,
+    );
+    let bb = BoundingBox::new(Point::new(0.0, 0.0), Point::new(5.0, 10.0));
+    let pos = match spawn_on_surface(world, "ally-melee1", Point::new(1.0, 340.0), &walk, bb) {
+        Some(pos) => pos,
+        None => return false,
+    };
+
     world
         .create_entity()
         .with(Ally)
+        .with(soldier_status_effects().with_effect(StatusEffect::timed(
+            StatusEffectKind::Invulnerable,
+            SPAWN_PROTECTION_TIME,
+        )))
         .with(Sprite::new(soldier_sprite))
-        .with(WorldPosition(Point::new(1.0, 340.0)))
-        .with(Walk::new(
-            BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
-            15.0,
-        ))
-        .with(BoundingBox::new(
-            Point::new(0.0, 0.0),
-            Point::new(5.0, 10.0),
-        ))
+        .with(pos)
+        .with(walk)
+        .with(Footprints::new(FOOTPRINT_INTERVAL))
+        .with(bb)
         .with(Destination(1280.0))
         .with(Health(health))
-        .with(HealthBar {
-            health,
+        .with(HealthBar::new(health, 10, (-2, -3)))
+        .with(Melee::new(10.0, 1.0, DamageType::Crush))
+        .with(Veterancy::new(UnitStats {
             max_health: health,
-            width: 10,
-            pos: Point2::new(0, 0),
-            offset: (-2, -3),
-        })
-        .with(Melee::new(10.0, 1.0))
+            melee_damage: 10.0,
+            projectile_damage: 0.0,
+            walk_speed: 15.0,
+        }))
+        .with(Resistance(&SOLDIER_RESISTANCE))
+        .with(Stagger::new(0.5))
+        .with(Morale::new(0.2, 0.6))
         .with(UnitState::Walk)
         .build();
+
+    true
+}
+
+// The armor of soldiers keeps poison from stacking up
+fn soldier_status_effects() -> StatusEffects {
+    StatusEffects::default().with_stacking(StatusEffectKind::Poison, Stacking::Refresh)
+}
+
+/// Build an enemy catapult on the terrain surface at the horizontal position, its boulders split
+/// into smaller ones at the top of their flight.
+///
+/// Returns false when there is no ground to build on.
+pub fn build_catapult(world: &mut World, x: f64) -> bool {
+    let surface = match world
+        .read_resource::<Terrain>()
+        .surface_below(Point::new(x, 0.0))
+    {
+        Some(surface) => surface,
+        None => return false,
+    };
+    let (projectile1, bighole1) = {
+        let images = &*world.read_resource::<Images>();
+
+        (
+            *images.0.get("projectile1").unwrap(),
+            *images.0.get("bighole1").unwrap(),
+        )
+    };
+    let tuning = *world.read_resource::<Tuning>();
+
+    world
+        .create_entity()
+        .with(Enemy)
+        .with(Turret {
+            delay: 4.0 * tuning.enemy_turret_delay,
+            min_distance: 50.0,
+            max_strength: 310.0,
+            flight_time: 5.0,
+            strength_variation: 0.05,
+            ..Turret::default()
+        })
+        .with(Point::new(x, surface.y - 5.0))
+        .with(ProjectileSprite(Sprite::new(projectile1)))
+        .with(Trail::new(16, 0.1, ROCK_TRAIL_COLOR))
+        .with(MaskId {
+            id: bighole1,
+            size: (5, 5),
+        })
+        .with(ProjectileBoundingBox(BoundingBox::new(
+            Point::new(0.0, 0.0),
+            Point::new(5.0, 5.0),
+        )))
+        .with(Damage(30.0 * tuning.enemy_damage))
+        .with(DamageType::Crush)
+        .with(Explosion::new(
+            20.0,
+            15.0 * tuning.enemy_damage,
+            Falloff::Linear,
+        ))
+        .with(Split::new(SplitTrigger::Apex, 3, 0.6, 1.0))
+        .build();
+
+    true
+}
+
+/// Build an enemy tower on the terrain surface at the horizontal position that shoots volleys of
+/// poisoned arrows.
+///
+/// Returns false when there is no ground to build on.
+pub fn build_arrow_tower(world: &mut World, x: f64) -> bool {
+    let surface = match world
+        .read_resource::<Terrain>()
+        .surface_below(Point::new(x, 0.0))
+    {
+        Some(surface) => surface,
+        None => return false,
+    };
+    let tuning = *world.read_resource::<Tuning>();
+
+    world
+        .create_entity()
+        .with(Enemy)
+        .with(Turret {
+            delay: 2.0 * tuning.enemy_turret_delay,
+            min_distance: 50.0,
+            max_strength: 290.0,
+            flight_time: 4.0,
+            strength_variation: 0.05,
+            volley_count: 3,
+            volley_spread: 0.08,
+            volley_pattern: VolleyPattern::Random,
+            ..Turret::default()
+        })
+        .with(Point::new(x, surface.y - 10.0))
+        .with(Arrow(7.0))
+        .with(Line::new(WOOD_COLOR))
+        .with(Trail::new(4, 0.05, ARROW_TRAIL_COLOR))
+        .with(ProjectileBoundingBox(BoundingBox::new(
+            Point::new(0.0, 0.0),
+            Point::new(1.0, 1.0),
+        )))
+        .with(Damage(5.0 * tuning.enemy_damage))
+        .with(DamageType::Pierce)
+        .with(StatusEffectOnHit(StatusEffect::poison(
+            2.0 * tuning.enemy_damage,
+            3.0,
+        )))
+        .build();
+
+    true
 }
 
 pub fn place_turrets(world: &mut World, level: u8) {
@@ -107,13 +498,14 @@ pub fn place_turrets(world: &mut World, level: u8) {
             *images.0.get("enemy-archer1").unwrap(),
         )
     };
+    let tuning = *world.read_resource::<Tuning>();
 
     if level == 1 {
         world
             .create_entity()
             .with(Enemy)
             .with(Turret {
-                delay: 3.0,
+                delay: 3.0 * tuning.enemy_turret_delay,
                 min_distance: 50.0,
                 max_strength: 310.0,
                 flight_time: 5.0,
@@ -122,6 +514,7 @@ pub fn place_turrets(world: &mut World, level: u8) {
             })
             .with(Point::new(1270.0, 295.0))
             .with(ProjectileSprite(Sprite::new(projectile1)))
+            .with(Trail::new(16, 0.1, ROCK_TRAIL_COLOR))
             .with(MaskId {
                 id: bighole1,
                 size: (5, 5),
@@ -130,14 +523,20 @@ pub fn place_turrets(world: &mut World, level: u8) {
                 Point::new(0.0, 0.0),
                 Point::new(5.0, 5.0),
             )))
-            .with(Damage(30.0))
+            .with(Damage(30.0 * tuning.enemy_damage))
+            .with(DamageType::Crush)
+            .with(Explosion::new(
+                20.0,
+                15.0 * tuning.enemy_damage,
+                Falloff::Linear,
+            ))
             .build();
 
         world
             .create_entity()
             .with(Enemy)
             .with(Turret {
-                delay: 1.0,
+                delay: 1.0 * tuning.enemy_turret_delay,
                 min_distance: 50.0,
                 max_strength: 290.0,
                 flight_time: 4.0,
@@ -147,82 +546,130 @@ pub fn place_turrets(world: &mut World, level: u8) {
             .with(Point::new(1255.0, 315.0))
             .with(Arrow(7.0))
             .with(Line::new(WOOD_COLOR))
+            .with(Trail::new(4, 0.05, ARROW_TRAIL_COLOR))
             .with(ProjectileBoundingBox(BoundingBox::new(
                 Point::new(0.0, 0.0),
                 Point::new(1.0, 1.0),
             )))
-            .with(Damage(10.0))
+            .with(Damage(10.0 * tuning.enemy_damage))
+            .with(DamageType::Pierce)
             .build();
 
         for i in 0..5 {
-            let health = 50.0;
+            let health = 50.0 * tuning.enemy_health;
+
+            let walk = Walk::new(
+                BoundingBox::new(Point::new(2.0, 5.0), Point::new(5.0, 10.0)),
+                15.0,
+            );
+            let bb = BoundingBox::new(Point::new(1.0, 0.0), Point::new(6.0, 10.0));
+            let pos = match spawn_on_surface(
+                world,
+                "enemy-melee1",
+                Point::new(1130.0 - 20.0 * i as f64, 320.0),
+                &walk,
+                bb,
+            ) {
+                Some(pos) => pos,
+                None => continue,
+            };
 
             world
                 .create_entity()
                 .with(Enemy)
                 .with(Sprite::new(enemy_soldier1))
-                .with(WorldPosition(Point::new(1130.0 - 20.0 * i as f64, 320.0)))
-                .with(Walk::new(
-                    BoundingBox::new(Point::new(2.0, 5.0), Point::new(5.0, 10.0)),
-                    15.0,
-                ))
-                .with(BoundingBox::new(
-                    Point::new(1.0, 0.0),
-                    Point::new(6.0, 10.0),
-                ))
+                .with(pos)
+                .with(walk)
+                .with(Footprints::new(FOOTPRINT_INTERVAL))
+                .with(bb)
                 .with(Destination(10.0))
                 .with(Health(health))
-                .with(HealthBar {
-                    health,
+                .with(HealthBar::new(health, 10, (-2, -3)))
+                .with(Melee::new(
+                    10.0 * tuning.enemy_damage,
+                    1.0,
+                    DamageType::Crush,
+                ))
+                .with(Veterancy::new(UnitStats {
                     max_health: health,
-                    width: 10,
-                    pos: Point2::new(0, 0),
-                    offset: (-2, -3),
-                })
-                .with(Melee::new(10.0, 1.0))
+                    melee_damage: 10.0 * tuning.enemy_damage,
+                    projectile_damage: 0.0,
+                    walk_speed: 15.0,
+                }))
+                .with(Resistance(&SOLDIER_RESISTANCE))
+                .with(Stagger::new(0.5))
+                .with(soldier_status_effects())
+                .with(Morale::new(0.2, 0.6))
                 .with(UnitState::Walk)
                 .build();
         }
 
         for i in 0..20 {
-            let health = 20.0;
+            let health = 20.0 * tuning.enemy_health;
+
+            let walk = Walk::new(
+                BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
+                20.0,
+            );
+            let bb = BoundingBox::new(Point::new(1.0, 0.0), Point::new(5.0, 10.0));
+            let pos = match spawn_on_surface(
+                world,
+                "enemy-archer1",
+                Point::new(1140.0 - 20.0 * i as f64, 320.0),
+                &walk,
+                bb,
+            ) {
+                Some(pos) => pos,
+                None => continue,
+            };
 
             world
                 .create_entity()
                 .with(Enemy)
                 .with(Sprite::new(enemy_archer1))
-                .with(WorldPosition(Point::new(1140.0 - 20.0 * i as f64, 320.0)))
-                .with(Walk::new(
-                    BoundingBox::new(Point::new(1.0, 5.0), Point::new(4.0, 10.0)),
-                    20.0,
-                ))
-                .with(BoundingBox::new(
-                    Point::new(1.0, 0.0),
-                    Point::new(5.0, 10.0),
-                ))
+                .with(pos)
+                .with(walk)
+                .with(Footprints::new(FOOTPRINT_INTERVAL))
+                .with(bb)
                 .with(Destination(10.0))
                 .with(Health(health))
-                .with(HealthBar {
-                    health,
+                .with(HealthBar::new(health, 5, (1, -3)))
+                .with(Melee::new(
+                    5.0 * tuning.enemy_damage,
+                    1.0,
+                    DamageType::Pierce,
+                ))
+                .with(Veterancy::new(UnitStats {
                     max_health: health,
-                    width: 5,
-                    pos: Point2::new(0, 0),
-                    offset: (1, -3),
-                })
-                .with(Melee::new(5.0, 1.0))
+                    melee_damage: 5.0 * tuning.enemy_damage,
+                    projectile_damage: 5.0 * tuning.enemy_damage,
+                    walk_speed: 20.0,
+                }))
+                .with(Stagger::new(1.0))
+                .with(StatusEffects::default())
+                .with(Morale::new(0.3, 0.7))
                 .with(Turret {
-                    delay: 3.0,
+                    delay: 3.0 * tuning.enemy_turret_delay,
                     min_distance: 20.0,
                     max_strength: 150.0,
                     flight_time: 2.0,
                     strength_variation: 0.1,
+                    line_of_sight: true,
                     ..Turret::default()
                 })
                 .with(TurretOffset((2.0, 2.0)))
                 .with(Point::new(0.0, 0.0))
                 .with(Arrow(3.0))
                 .with(Line::new(WOOD_COLOR))
-                .with(Damage(5.0))
+                .with(Trail::new(4, 0.05, ARROW_TRAIL_COLOR))
+                .with(Ricochet::new(
+                    ARROW_RICOCHET_ANGLE,
+                    ARROW_RICOCHET_SPEED,
+                    ARROW_RICOCHET_RESTITUTION,
+                    1,
+                ))
+                .with(Damage(5.0 * tuning.enemy_damage))
+                .with(DamageType::Pierce)
                 .with(ProjectileBoundingBox(BoundingBox::new(
                     Point::new(0.0, 0.0),
                     Point::new(1.0, 1.0),
@@ -231,5 +678,110 @@ pub fn place_turrets(world: &mut World, level: u8) {
                 .with(UnitState::Walk)
                 .build();
         }
+
+        // The windows only get a turret when an archer is garrisoned behind them
+        for (i, (x, y)) in LEVEL1_GARRISON_WINDOWS.iter().enumerate() {
+            world
+                .create_entity()
+                .with(Ally)
+                .with(GarrisonWindow(i))
+                .with(Point::new(*x, *y))
+                .with(Arrow(3.0))
+                .with(Line::new(WOOD_COLOR))
+                .with(Trail::new(4, 0.05, ARROW_TRAIL_COLOR))
+                .with(Damage(5.0))
+                .with(DamageType::Pierce)
+                .with(ProjectileBoundingBox(BoundingBox::new(
+                    Point::new(0.0, 0.0),
+                    Point::new(1.0, 1.0),
+                )))
+                .with(IgnoreCollision::Ally)
+                .with(GarrisonShot)
+                .build();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn props_are_parsed_line_by_line() {
+        assert_eq!(parse_props(LEVEL1_PROPS).unwrap().len(), 7);
+        assert_eq!(
+            parse_props("# comment\n\n  rock 12.5\ntree 3\n").unwrap(),
+            vec![("rock".to_string(), 12.5), ("tree".to_string(), 3.0)]
+        );
+
+        assert_eq!(
+            parse_props("rock 1\nrock left").unwrap_err(),
+            "line 2: invalid position \"left\""
+        );
+        assert_eq!(
+            parse_props("rock").unwrap_err(),
+            "line 1: expected \"<sprite> <x>\""
+        );
+    }
+
+    #[test]
+    fn props_from_mods_replace_the_level_props() {
+        let dir = std::env::temp_dir().join(format!("castle-game-props-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let levels = dir.join("props").join("levels");
+        std::fs::create_dir_all(&levels).unwrap();
+        std::fs::write(levels.join(LEVEL1_PROPS_FILE), "missing 5\n").unwrap();
+        let mods = Mods::load_from(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut world = World::new();
+        world.insert(Terrain::new((20, 20)));
+        world.insert(Images(HashMap::new()));
+        world.insert(EventLog::default());
+        world.insert(mods);
+        place_props(&mut world, &mut Render::new((20, 20)), 1);
+
+        let events = world.read_resource::<EventLog>();
+        let texts: Vec<&str> = events.last(10).map(|event| event.text.as_str()).collect();
+        assert_eq!(texts, ["prop \"missing\" is not a known sprite"]);
+    }
+
+    #[test]
+    fn merged_prop_is_destructible_terrain() {
+        let mut terrain = Terrain::new((20, 20));
+        for y in 15..20 {
+            for x in 0..20 {
+                terrain.draw_pixel((x, y), 0xFF_80_60_40);
+            }
+        }
+        // Merge a prop of 3 by 3 pixels on the surface like the sprite would be, before the
+        // materials are generated
+        for y in 12..15 {
+            for x in 9..12 {
+                terrain.draw_pixel((x, y), 0xFF_90_90_90);
+            }
+        }
+        terrain.generate_materials(18);
+        assert_eq!(
+            terrain
+                .surface_below(Point::new(10.0, 0.0))
+                .map(|surface| surface.y),
+            Some(12.0)
+        );
+
+        // The prop is dirt so a small crater removes it completely
+        terrain.remove_with((10, 13), (4, 4), |buffer| {
+            for y in 11..15 {
+                for x in 8..12 {
+                    buffer[x + y * 20] = 0xFF_FF_00_FF;
+                }
+            }
+        });
+        assert_eq!(
+            terrain
+                .surface_below(Point::new(10.0, 0.0))
+                .map(|surface| surface.y),
+            Some(15.0)
+        );
     }
 }
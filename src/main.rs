@@ -1,14 +1,32 @@
 mod ai;
 mod audio;
+mod ballista;
+mod console;
+mod difficulty;
 mod draw;
+mod ease;
+mod edge;
+mod events;
+mod explosion;
+mod garrison;
 mod geom;
 mod gui;
+mod input;
 mod level;
+mod mods;
+mod morale;
+mod options;
 mod physics;
 mod projectile;
+mod protection;
+mod stats;
+mod status;
 mod terrain;
+mod territory;
+mod throw;
 mod turret;
 mod unit;
+mod veterancy;
 
 use minifb::*;
 use rust_embed::RustEmbed;
@@ -21,40 +39,75 @@ use std::{
 
 use ai::*;
 use audio::Audio;
+use ballista::*;
+use console::Console;
+use difficulty::*;
 use draw::*;
+use ease::*;
+use edge::*;
+use events::*;
+use explosion::*;
+use garrison::*;
 use geom::*;
 use gui::*;
+use input::*;
 use level::*;
+use mods::Mods;
+use morale::*;
+use options::*;
 use physics::*;
 use projectile::*;
+use protection::*;
+use stats::*;
+use status::*;
 use terrain::*;
+use territory::*;
+use throw::*;
 use turret::*;
 use unit::*;
+use veterancy::*;
 
 const WIDTH: usize = 1280;
 const HEIGHT: usize = 540;
 
 const GRAVITY: f64 = 98.1;
 
+// Everything below this height in the level is made of rock
+const ROCK_LEVEL: usize = 400;
+
+// Amount of events shown in the corner and when the event log is expanded
+const RECENT_EVENT_LINES: usize = 5;
+const EXPANDED_EVENT_LINES: usize = 40;
+
 #[derive(RustEmbed)]
 #[folder = "$OUT_DIR/sprites/"]
 struct SpriteFolder;
 
 impl SpriteFolder {
-    fn load_sprite(render: &mut Render, resources: &mut HashMap<String, usize>, name: &str) {
+    fn load_sprite(
+        render: &mut Render,
+        resources: &mut HashMap<String, usize>,
+        mods: &Mods,
+        name: &str,
+    ) {
         let mut file = name.to_owned();
         file.push_str(".blit");
 
-        let buf = Self::get(&*file).unwrap();
+        let buf = mods.get("sprites", &file, Self::get(&file)).unwrap();
 
         resources.insert(name.to_string(), render.add_buf_from_memory(name, &buf));
     }
 
-    fn load_anim(render: &mut Render, resources: &mut HashMap<String, usize>, name: &str) {
+    fn load_anim(
+        render: &mut Render,
+        resources: &mut HashMap<String, usize>,
+        mods: &Mods,
+        name: &str,
+    ) {
         let mut file = name.to_owned();
         file.push_str(".anim");
 
-        let buf = Self::get(&*file).unwrap();
+        let buf = mods.get("sprites", &file, Self::get(&file)).unwrap();
 
         resources.insert(
             name.to_string(),
@@ -68,34 +121,23 @@ impl SpriteFolder {
 struct MaskFolder;
 
 impl MaskFolder {
-    fn load_sprite(render: &mut Render, resources: &mut HashMap<String, usize>, name: &str) {
+    fn load_sprite(
+        render: &mut Render,
+        resources: &mut HashMap<String, usize>,
+        mods: &Mods,
+        name: &str,
+    ) {
         let mut file = name.to_owned();
         file.push_str(".blit");
 
-        let buf = Self::get(&*file).unwrap();
+        let buf = mods.get("masks", &file, Self::get(&file)).unwrap();
 
         resources.insert(name.to_string(), render.add_buf_from_memory(name, &buf));
     }
 }
 
-fn main() {
-    let mut buffer: Vec<u32> = vec![0; (WIDTH * HEIGHT) as usize];
-
-    let mut render = Render::new((WIDTH, HEIGHT));
-
-    let mut resources = HashMap::new();
-
-    SpriteFolder::load_anim(&mut render, &mut resources, "ally-archer1");
-    SpriteFolder::load_sprite(&mut render, &mut resources, "ally-melee1");
-    SpriteFolder::load_sprite(&mut render, &mut resources, "enemy-melee1");
-    SpriteFolder::load_sprite(&mut render, &mut resources, "enemy-archer1");
-    SpriteFolder::load_sprite(&mut render, &mut resources, "projectile1");
-
-    MaskFolder::load_sprite(&mut render, &mut resources, "bighole1");
-
-    // Setup game related things
-    let mut world = World::new();
-
+/// Register all the components used by the systems.
+fn register_components(world: &mut World) {
     // draw.rs
     world.register::<PixelParticle>();
     world.register::<MaskId>();
@@ -122,13 +164,26 @@ fn main() {
     // unit.rs
     world.register::<UnitState>();
     world.register::<Health>();
+    world.register::<Resistance>();
     world.register::<HealthBar>();
     world.register::<Walk>();
+    world.register::<Footprints>();
+    world.register::<Stagger>();
+    world.register::<Morale>();
+    world.register::<Veterancy>();
+    world.register::<StatusEffects>();
 
     // turret.rs
     world.register::<Turret>();
     world.register::<TurretOffset>();
 
+    // ballista.rs
+    world.register::<Ballista>();
+
+    // garrison.rs
+    world.register::<GarrisonWindow>();
+    world.register::<GarrisonShot>();
+
     // projectile.rs
     world.register::<Projectile>();
     world.register::<ProjectileSprite>();
@@ -136,9 +191,57 @@ fn main() {
     world.register::<IgnoreCollision>();
     world.register::<Arrow>();
     world.register::<Damage>();
+    world.register::<Owner>();
+    world.register::<DamageType>();
+    world.register::<GravityScale>();
+    world.register::<Drag>();
+    world.register::<MaxSpeed>();
+    world.register::<Trail>();
+    world.register::<Ricochet>();
+    world.register::<Split>();
+    world.register::<Explosion>();
+    world.register::<AreaDamage>();
+    world.register::<StatusEffectOnHit>();
 
     // gui.rs
     world.register::<FloatingText>();
+}
+
+fn main() {
+    // Parse the command line options, they can't be changed during a battle
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+
+    let mut render = Render::new((WIDTH, HEIGHT));
+
+    let mut resources = HashMap::new();
+
+    // Assets in the mods directory replace the embedded ones
+    let mods = Mods::load();
+
+    SpriteFolder::load_anim(&mut render, &mut resources, &mods, "ally-archer1");
+    SpriteFolder::load_sprite(&mut render, &mut resources, &mods, "ally-melee1");
+    SpriteFolder::load_sprite(&mut render, &mut resources, &mods, "enemy-melee1");
+    SpriteFolder::load_sprite(&mut render, &mut resources, &mods, "enemy-archer1");
+    SpriteFolder::load_sprite(&mut render, &mut resources, &mods, "projectile1");
+
+    MaskFolder::load_sprite(&mut render, &mut resources, &mods, "bighole1");
+
+    // Setup game related things
+    let mut world = World::new();
+
+    register_components(&mut world);
 
     // Resources to `Fetch`
     world.insert(Terrain::new((WIDTH, HEIGHT)));
@@ -146,18 +249,51 @@ fn main() {
     world.insert(DeltaTime::new(1.0 / 60.0));
     world.insert(Images(resources));
     world.insert(Audio::new());
-
-    render.draw_background_from_memory(&SpriteFolder::get("background.blit").unwrap());
+    world.insert(SpearThrow::default());
+    world.insert(Statistics::default());
+    world.insert(EventLog::default());
+    world.insert(Tuning::new(options.difficulty));
+    world.insert(edge_behavior(options.level));
+    world.insert(protection_zones(options.level));
+    world.insert(FrontLine::default());
+    world.insert(garrison(options.level));
+
+    render.draw_background_from_memory(
+        &mods
+            .get(
+                "sprites",
+                "background.blit",
+                SpriteFolder::get("background.blit"),
+            )
+            .unwrap(),
+    );
     render.draw_terrain_from_memory(
-        &mut *world.write_resource::<Terrain>(),
-        &SpriteFolder::get("level.blit").unwrap(),
+        &mut world.write_resource::<Terrain>(),
+        &mods
+            .get("sprites", "level.blit", SpriteFolder::get("level.blit"))
+            .unwrap(),
     );
+    {
+        let mut events = world.write_resource::<EventLog>();
+        for warning in mods.warnings() {
+            events.push(EventCategory::Warning, warning.as_str());
+        }
+    }
+    // Keep the list of mods around for the console
+    world.insert(mods);
+    place_props(&mut world, &mut render, options.level);
+    world
+        .write_resource::<Terrain>()
+        .generate_materials(ROCK_LEVEL);
 
-    place_turrets(&mut world, 1);
+    place_turrets(&mut world, options.level);
 
     let mut dispatcher = DispatcherBuilder::new()
         .with(ProjectileSystem, "projectile", &[])
         .with(ArrowSystem, "arrow", &["projectile"])
+        .with(SplitSystem, "split", &["projectile"])
+        .with(TrailSystem, "trail", &["projectile"])
+        .with(AreaDamageSystem, "area_damage", &["projectile"])
         .with(
             ProjectileCollisionSystem,
             "projectile_collision",
@@ -169,18 +305,49 @@ fn main() {
             &["projectile"],
         )
         .with(TerrainCollapseSystem, "terrain_collapse", &["projectile"])
+        .with(TerrainFadeSystem, "terrain_fade", &[])
         .with(WalkSystem, "walk", &[])
-        .with(UnitFallSystem, "unit_fall", &["walk"])
+        .with(StaggerSystem, "stagger", &["walk"])
+        .with(UnitFallSystem, "unit_fall", &["walk", "stagger"])
+        .with(FootprintSystem, "footprint", &["unit_fall"])
+        .with(EdgeSystem, "edge", &["projectile", "unit_fall"])
         .with(UnitResumeWalkingSystem, "unit_resume_walking", &["walk"])
         .with(UnitCollideSystem, "unit_collide", &["walk"])
         .with(MeleeSystem, "melee", &["walk"])
+        .with(ProtectionSystem, "protection", &["walk"])
+        .with(
+            StatusEffectSystem,
+            "status_effect",
+            &["melee", "protection", "area_damage"],
+        )
+        .with(
+            MoraleSystem,
+            "morale",
+            &[
+                "melee",
+                "projectile_collision",
+                "area_damage",
+                "status_effect",
+            ],
+        )
+        .with(FrontLineSystem, "front_line", &["morale"])
+        .with(
+            VeterancySystem,
+            "veterancy",
+            &["melee", "projectile_collision"],
+        )
         .with(HealthBarSystem, "health_bar", &["walk"])
         .with(TurretUnitSystem, "turret_unit", &["walk"])
-        .with(TurretSystem, "turret", &["turret_unit"])
+        .with(GarrisonSystem, "garrison", &[])
+        .with(BallistaSystem, "ballista", &["walk"])
+        .with(TurretSystem, "turret", &["turret_unit", "garrison"])
+        .with(SpearThrowSystem, "spear_throw", &[])
         .with(SpriteSystem, "sprite", &["projectile", "walk"])
         .with(AnimSystem, "anim", &["projectile", "walk"])
         .with(ParticleSystem, "particle", &[])
         .with(FloatingTextSystem, "floating_text", &[])
+        .with(StatisticsSystem, "statistics", &[])
+        .with(EventLogSystem, "event_log", &[])
         .build();
 
     // Setup minifb window related things
@@ -191,7 +358,7 @@ fn main() {
     let options = WindowOptions {
         borderless: false,
         title: true,
-        scale: Scale::X2,
+        scale: options.scale,
         scale_mode: ScaleMode::AspectRatioStretch,
         ..Default::default()
     };
@@ -199,6 +366,8 @@ fn main() {
 
     // Setup the GUI system
     let mut gui = IngameGui::new((WIDTH as i32, HEIGHT as i32));
+    let mut console = Console::new(&mut window);
+    let mut mouse = Mouse::default();
 
     {
         // Start the audio
@@ -217,13 +386,37 @@ fn main() {
         }
 
         // Handle mouse events
-        if let Some(mouse) = window.get_mouse_pos(MouseMode::Discard) {
-            gui.handle_mouse(
-                (mouse.0 as i32, mouse.1 as i32),
-                window.get_mouse_down(MouseButton::Left),
-            );
+        let dt = world.read_resource::<DeltaTime>().to_seconds();
+        for event in mouse.update(&mut window, dt) {
+            match event {
+                MouseEvent::Cancel(MouseButton::Left) => gui.cancel_press(),
+                MouseEvent::Cancel(MouseButton::Right) => {
+                    world.write_resource::<SpearThrow>().cancel()
+                }
+                _ => (),
+            }
+        }
+        if let Some(pos) = mouse.pos() {
+            gui.handle_mouse(pos, mouse.is_down(MouseButton::Left));
+            world
+                .write_resource::<SpearThrow>()
+                .handle_mouse(pos, mouse.is_down(MouseButton::Right));
         };
 
+        // Run the debug commands before the systems so they can't change anything halfway
+        console.handle_keys(&window);
+        console.run_queued(&mut world);
+
+        // Move the archers at the castle door inside and let the last one out again
+        if !console.is_open() {
+            if window.is_key_pressed(Key::G, KeyRepeat::No) {
+                garrison_archers(&mut world);
+            }
+            if window.is_key_pressed(Key::U, KeyRepeat::No) {
+                ungarrison_archer(&mut world);
+            }
+        }
+
         dispatcher.dispatch(&world);
 
         // Add/remove entities added in dispatch through `LazyUpdate`
@@ -231,7 +424,30 @@ fn main() {
 
         // Render the sprites & masks
         {
-            render.draw_terrain_and_background(&mut buffer, &*world.write_resource::<Terrain>());
+            render.draw_terrain_and_background(&mut buffer, &mut world.write_resource::<Terrain>());
+
+            // Render the shadows below the projectiles and the feet of the units
+            {
+                let terrain = world.read_resource::<Terrain>();
+                let positions = world.read_storage::<WorldPosition>();
+                let projectiles = world.read_storage::<Projectile>();
+                let walks = world.read_storage::<Walk>();
+                for (pos, _) in (&positions, &projectiles).join() {
+                    render.draw_shadow(&mut buffer, &terrain, pos.0);
+                }
+                for (pos, walk) in (&positions, &walks).join() {
+                    let feet = Point::new(
+                        pos.0.x + (walk.bounds.min.x + walk.bounds.max.x) / 2.0,
+                        pos.0.y + walk.bounds.max.y,
+                    );
+                    render.draw_shadow(&mut buffer, &terrain, feet);
+                }
+            }
+
+            // Render the projectile trails below the sprites
+            for trail in world.read_storage::<Trail>().join() {
+                render.draw_trail(&mut buffer, trail);
+            }
 
             let mut anims = world.write_storage::<Anim>();
             let sprites = world.read_storage::<Sprite>();
@@ -239,6 +455,10 @@ fn main() {
             let pixels = world.read_storage::<PixelParticle>();
             let terrain_masks = world.read_storage::<TerrainMask>();
             let health_bars = world.read_storage::<HealthBar>();
+            let morales = world.read_storage::<Morale>();
+            let status_effects = world.read_storage::<StatusEffects>();
+            let veterancies = world.read_storage::<Veterancy>();
+            let show_morale = !console.is_open() && window.is_key_down(Key::M);
             for entity in world.entities().join() {
                 if let Some(anim) = anims.get_mut(entity) {
                     render
@@ -265,19 +485,90 @@ fn main() {
                         &mut buffer,
                         health_bar.pos,
                         health_bar.health / health_bar.max_health,
+                        health_bar.delayed_health / health_bar.max_health,
                         health_bar.width,
                     );
+
+                    // Mark the units with a status effect with its color in front of the health
+                    if let Some(tint) = status_effects
+                        .get(entity)
+                        .and_then(|effects| effects.tint())
+                    {
+                        if health_bar.pos.x > 0 {
+                            let mut tint_pos = health_bar.pos;
+                            tint_pos.x -= 1;
+                            render.draw_foreground_pixel(&mut buffer, tint_pos, tint);
+                        }
+                    }
+
+                    // Show the level of the unit as rows of chevrons above the health
+                    if let Some(veterancy) = veterancies.get(entity) {
+                        let center = health_bar.pos.x + health_bar.width / 2;
+                        for row in 0..veterancy.level() {
+                            let y = match health_bar.pos.y.checked_sub(2 + row * 2) {
+                                Some(y) => y,
+                                None => break,
+                            };
+                            let mut chevron = health_bar.pos;
+                            chevron.y = y;
+                            for x in center.saturating_sub(1)..=center + 1 {
+                                chevron.x = x;
+                                render.draw_foreground_pixel(&mut buffer, chevron, CHEVRON_COLOR);
+                            }
+                        }
+                    }
+
+                    // Show the morale below the health while M is held
+                    if let (Some(morale), true) = (morales.get(entity), show_morale) {
+                        let mut morale_pos = health_bar.pos;
+                        morale_pos.y += 2;
+                        render.draw_healthbar(
+                            &mut buffer,
+                            morale_pos,
+                            morale.value,
+                            morale.value,
+                            health_bar.width,
+                        );
+                    }
                 }
 
                 if let Some(mask) = terrain_masks.get(entity) {
                     render
-                        .draw_mask_terrain(&mut *world.write_resource::<Terrain>(), mask)
+                        .draw_mask_terrain(&mut world.write_resource::<Terrain>(), mask)
                         .unwrap();
 
                     // Immediately remove the mask after drawing it
                     let _ = world.entities().delete(entity);
                 }
             }
+
+            // Render the predicted arc of the spear the player is charging
+            for point in world.read_resource::<SpearThrow>().arc.iter() {
+                render.draw_foreground_pixel(&mut buffer, *point, PREDICTION_COLOR);
+            }
+
+            // Render the front line between the positions held by both sides
+            if let Some(x) = world.read_resource::<FrontLine>().x() {
+                render.draw_dashed_vertical_line(
+                    &mut buffer,
+                    x as usize,
+                    FRONT_LINE_DASH,
+                    FRONT_LINE_COLOR,
+                );
+            }
+
+            // Show where recruits are protected while the recruit buttons are hovered
+            if gui.recruit_hovered() {
+                let zone = world.read_resource::<ProtectionZones>().ally;
+                for x in [zone.min_x, zone.max_x].iter() {
+                    render.draw_dashed_vertical_line(
+                        &mut buffer,
+                        *x as usize,
+                        ZONE_OUTLINE_DASH,
+                        ZONE_OUTLINE_COLOR,
+                    );
+                }
+            }
         }
 
         // Update the gui system and receive a possible event
@@ -291,6 +582,14 @@ fn main() {
             _ => (),
         }
 
+        // Show how many archers are inside the castle next to the recruit buttons
+        {
+            let garrison = world.read_resource::<Garrison>();
+            if garrison.capacity() > 0 {
+                gui.draw_garrison(&mut buffer, garrison.units.len(), garrison.capacity());
+            }
+        }
+
         // Render the floating text
         let floating_texts = world.read_storage::<FloatingText>();
 
@@ -302,6 +601,40 @@ fn main() {
             }
         }
 
+        // Render the recent events in the top right corner, or the whole log while L is held
+        {
+            let events = world.read_resource::<EventLog>();
+            let lines: Vec<String> = if !console.is_open() && window.is_key_down(Key::L) {
+                events.last(EXPANDED_EVENT_LINES).map(Event::line).collect()
+            } else {
+                events.recent(RECENT_EVENT_LINES).map(Event::line).collect()
+            };
+            for (i, line) in lines.iter().enumerate() {
+                gui.draw_label(&mut buffer, line, (WIDTH as i32 - 200, 8 + i as i32 * 10));
+            }
+        }
+
+        // Render the battle statistics while tab is held
+        if !console.is_open() && window.is_key_down(Key::Tab) {
+            let stats = world.read_resource::<Statistics>();
+            let front_line = world.read_resource::<FrontLine>();
+            for (i, line) in stats
+                .summary()
+                .iter()
+                .chain(front_line.summary().iter())
+                .enumerate()
+            {
+                gui.draw_label(&mut buffer, line, (8, 8 + i as i32 * 10));
+            }
+        }
+
+        // Render the debug console on top of everything
+        if console.is_open() {
+            for (i, line) in console.lines().enumerate() {
+                gui.draw_label(&mut buffer, &line, (8, 8 + i as i32 * 10));
+            }
+        }
+
         // Finally draw the buffer on the window
         window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
 
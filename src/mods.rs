@@ -0,0 +1,211 @@
+use blit::{AnimationBlitBuffer, BlitBuffer};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Directory next to the executable containing a sub directory for every mod
+const MODS_DIR: &str = "mods";
+// The asset folders a mod can override, they mirror the embedded folders and the level data
+const ASSET_FOLDERS: [&str; 3] = ["sprites", "masks", "levels"];
+
+// An asset of a mod with its folder and file name
+type ModFile = ((String, String), Vec<u8>);
+
+/// Assets from the mods directory that override the built-in ones.
+#[derive(Debug, Default)]
+pub struct Mods {
+    names: Vec<String>,
+    // The contents of the overridden files by folder and file name
+    files: HashMap<(String, String), Vec<u8>>,
+    // Disabled mods and conflicts between mods, shown in the event log
+    warnings: Vec<String>,
+}
+
+impl Mods {
+    /// Load the mods from the directory next to the executable.
+    pub fn load() -> Self {
+        match std::env::current_exe() {
+            Ok(exe) => match exe.parent() {
+                Some(dir) => Mods::load_from(&dir.join(MODS_DIR)),
+                None => Mods::default(),
+            },
+            Err(_) => Mods::default(),
+        }
+    }
+
+    /// Load every mod in the directory in alphabetical order, later mods override earlier ones.
+    pub fn load_from(dir: &Path) -> Self {
+        let mut mods = Mods::default();
+
+        let mut mod_dirs: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect(),
+            // Playing without mods is fine
+            Err(_) => return mods,
+        };
+        mod_dirs.sort();
+
+        // Which mod overrides which file, to report conflicts
+        let mut owners: HashMap<(String, String), String> = HashMap::new();
+
+        for mod_dir in mod_dirs {
+            let name = mod_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            // A broken asset disables the whole mod so it can't be half applied
+            let files = match read_mod(&mod_dir) {
+                Ok(files) => files,
+                Err(err) => {
+                    mods.warnings
+                        .push(format!("mod \"{}\" is disabled: {}", name, err));
+                    continue;
+                }
+            };
+
+            for (key, bytes) in files {
+                if let Some(owner) = owners.insert(key.clone(), name.clone()) {
+                    mods.warnings.push(format!(
+                        "mod \"{}\" overrides \"{}/{}\" of mod \"{}\"",
+                        name, key.0, key.1, owner
+                    ));
+                }
+                mods.files.insert(key, bytes);
+            }
+
+            mods.names.push(name);
+        }
+
+        mods
+    }
+
+    /// The names of the mods that are applied, in the order they are applied.
+    pub fn mods_loaded(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The problems found while loading the mods.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// The file from the mods, or the built-in file when no mod overrides it.
+    pub fn get<'a>(
+        &'a self,
+        folder: &str,
+        file: &str,
+        builtin: Option<Cow<'static, [u8]>>,
+    ) -> Option<Cow<'a, [u8]>> {
+        match self.files.get(&(folder.to_string(), file.to_string())) {
+            Some(bytes) => Some(Cow::Borrowed(bytes)),
+            None => builtin,
+        }
+    }
+}
+
+// Read and validate all the assets of a single mod
+fn read_mod(dir: &Path) -> Result<Vec<ModFile>, String> {
+    let mut files = Vec::new();
+
+    for folder in ASSET_FOLDERS.iter() {
+        let entries = match fs::read_dir(dir.join(folder)) {
+            Ok(entries) => entries,
+            // A mod doesn't have to override every folder
+            Err(_) => continue,
+        };
+
+        for entry in entries {
+            let path = entry.map_err(|err| err.to_string())?.path();
+            let file = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let bytes = fs::read(&path).map_err(|err| format!("{}/{}: {}", folder, file, err))?;
+
+            let valid = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("blit") => BlitBuffer::from_memory(&bytes).is_ok(),
+                Some("anim") if *folder == "sprites" => {
+                    AnimationBlitBuffer::from_memory(&bytes).is_ok()
+                }
+                Some("props") if *folder == "levels" => matches!(
+                    std::str::from_utf8(&bytes).map(crate::parse_props),
+                    Ok(Ok(_))
+                ),
+                _ => return Err(format!("{}/{}: unknown asset type", folder, file)),
+            };
+            if !valid {
+                return Err(format!("{}/{}: invalid asset", folder, file));
+            }
+
+            files.push(((folder.to_string(), file), bytes));
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_embed::RustEmbed;
+
+    fn sprite(name: &str) -> Vec<u8> {
+        crate::SpriteFolder::get(name).unwrap().into_owned()
+    }
+
+    fn write_asset(dir: &Path, mod_name: &str, folder: &str, file: &str, bytes: &[u8]) {
+        let folder = dir.join(mod_name).join(folder);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join(file), bytes).unwrap();
+    }
+
+    #[test]
+    fn mods_override_in_order_and_broken_mods_are_disabled() {
+        let dir = std::env::temp_dir().join(format!("castle-game-mods-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let first = sprite("ally-melee1.blit");
+        let second = sprite("enemy-melee1.blit");
+        write_asset(&dir, "a-first", "sprites", "projectile1.blit", &first);
+        write_asset(&dir, "b-second", "sprites", "projectile1.blit", &second);
+        write_asset(&dir, "c-broken", "sprites", "projectile1.blit", &first);
+        write_asset(&dir, "c-broken", "sprites", "projectile1.png", &first);
+
+        let mods = Mods::load_from(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mods.mods_loaded(), ["a-first", "b-second"]);
+        assert_eq!(
+            mods.get("sprites", "projectile1.blit", None).as_deref(),
+            Some(&second[..])
+        );
+        // Files that aren't overridden come from the game itself
+        let builtin: &'static [u8] = &[1, 2, 3];
+        assert_eq!(
+            mods.get("sprites", "background.blit", Some(Cow::Borrowed(builtin)))
+                .as_deref(),
+            Some(builtin)
+        );
+
+        assert_eq!(mods.warnings().len(), 2);
+        assert_eq!(
+            mods.warnings()[0],
+            "mod \"b-second\" overrides \"sprites/projectile1.blit\" of mod \"a-first\""
+        );
+        assert!(mods.warnings()[1].starts_with("mod \"c-broken\" is disabled"));
+    }
+
+    #[test]
+    fn missing_directory_has_no_mods() {
+        let mods = Mods::load_from(Path::new("/does/not/exist"));
+        assert!(mods.mods_loaded().is_empty());
+        assert!(mods.warnings().is_empty());
+    }
+}
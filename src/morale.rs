@@ -0,0 +1,287 @@
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+// Units within this distance are affected by deaths and count when checking the odds
+const MORALE_RADIUS: f64 = 60.0;
+const FRIENDLY_DEATH_MORALE: f64 = -0.15;
+const ENEMY_DEATH_MORALE: f64 = 0.1;
+// Morale lost for every point of damage taken
+const DAMAGE_MORALE: f64 = -0.01;
+
+// Seconds between checking whether a unit is outnumbered
+const OUTNUMBERED_CHECK_INTERVAL: f64 = 1.0;
+// A unit is outnumbered when there are more than this many enemies around it for every friend
+const OUTNUMBERED_RATIO: usize = 2;
+const OUTNUMBERED_MORALE: f64 = -0.05;
+
+// Morale recovered every second while fleeing, and while fleeing inside friendly territory
+const FLEE_RECOVERY: f64 = 0.05;
+const FRIENDLY_TERRITORY_RECOVERY: f64 = 0.25;
+
+/// Multiplier of the walking speed while fleeing.
+pub const FLEE_SPEED: f64 = 1.5;
+/// Width of the area in front of the own castle where fleeing units regroup.
+pub const FRIENDLY_TERRITORY: f64 = 50.0;
+
+/// The will of a unit to keep fighting, it flees back to its own castle when it runs out.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Morale {
+    /// Between 0.0 and 1.0, units start with full morale.
+    pub value: f64,
+    /// The unit starts fleeing when the morale drops below this value.
+    pub flee_below: f64,
+    /// A fleeing unit turns around when the morale is restored to this value.
+    pub recover_above: f64,
+
+    last_health: Option<f64>,
+    outnumbered_check_left: f64,
+}
+
+impl Morale {
+    pub fn new(flee_below: f64, recover_above: f64) -> Self {
+        Morale {
+            value: 1.0,
+            flee_below,
+            recover_above,
+
+            last_health: None,
+            outnumbered_check_left: OUTNUMBERED_CHECK_INTERVAL,
+        }
+    }
+
+    pub fn change(&mut self, amount: f64) {
+        self.value = (self.value + amount).clamp(0.0, 1.0);
+    }
+}
+
+/// Whether the unit is close to the castle on the opposite side of its destination.
+pub fn in_friendly_territory(pos: f64, dest: f64, level_width: f64) -> bool {
+    if dest > pos {
+        pos < FRIENDLY_TERRITORY
+    } else {
+        pos > level_width - FRIENDLY_TERRITORY
+    }
+}
+
+#[derive(SystemData)]
+pub struct MoraleSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    terrain: Read<'a, Terrain>,
+    ally: ReadStorage<'a, Ally>,
+    pos: ReadStorage<'a, WorldPosition>,
+    dest: ReadStorage<'a, Destination>,
+    health: ReadStorage<'a, Health>,
+    state: WriteStorage<'a, UnitState>,
+    morale: WriteStorage<'a, Morale>,
+    stats: Write<'a, Statistics>,
+    events: Write<'a, EventLog>,
+}
+
+pub struct MoraleSystem;
+impl<'a> System<'a> for MoraleSystem {
+    type SystemData = MoraleSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let level_width = system_data.terrain.size().0 as f64;
+        let deaths = system_data.events.take_deaths();
+
+        // The sides and positions of all units to count the friends and enemies nearby
+        let units: Vec<(bool, Point)> = (
+            system_data.ally.maybe(),
+            &system_data.pos,
+            &system_data.health,
+        )
+            .join()
+            .map(|(ally, pos, _)| (ally.is_some(), pos.0))
+            .collect();
+
+        for (e, pos, dest, health, state, morale) in (
+            &*system_data.entities,
+            &system_data.pos,
+            &system_data.dest,
+            &system_data.health,
+            &mut system_data.state,
+            &mut system_data.morale,
+        )
+            .join()
+        {
+            let is_ally = system_data.ally.get(e).is_some();
+
+            for (death_pos, death_is_ally) in deaths.iter() {
+                if pos.0.distance(**death_pos) < MORALE_RADIUS {
+                    if *death_is_ally == is_ally {
+                        morale.change(FRIENDLY_DEATH_MORALE);
+                    } else {
+                        morale.change(ENEMY_DEATH_MORALE);
+                    }
+                }
+            }
+
+            if let Some(last_health) = morale.last_health {
+                morale.change((last_health - health.0).max(0.0) * DAMAGE_MORALE);
+            }
+            morale.last_health = Some(health.0);
+
+            morale.outnumbered_check_left -= dt;
+            if morale.outnumbered_check_left <= 0.0 {
+                morale.outnumbered_check_left += OUTNUMBERED_CHECK_INTERVAL;
+
+                let (friends, enemies) = units
+                    .iter()
+                    .filter(|(_, unit_pos)| pos.0.distance(**unit_pos) < MORALE_RADIUS)
+                    .fold((0, 0), |(friends, enemies), (unit_is_ally, _)| {
+                        if *unit_is_ally == is_ally {
+                            (friends + 1, enemies)
+                        } else {
+                            (friends, enemies + 1)
+                        }
+                    });
+                // The unit itself is counted as a friend
+                if enemies > friends * OUTNUMBERED_RATIO {
+                    morale.change(OUTNUMBERED_MORALE);
+                }
+            }
+
+            if *state == UnitState::Flee {
+                if in_friendly_territory(pos.0.x, dest.0, level_width) {
+                    morale.change(FRIENDLY_TERRITORY_RECOVERY * dt);
+                } else {
+                    morale.change(FLEE_RECOVERY * dt);
+                }
+
+                // Different thresholds for fleeing and returning prevent flipping between them
+                if morale.value >= morale.recover_above {
+                    *state = UnitState::Walk;
+                }
+            } else if morale.value < morale.flee_below {
+                *state = UnitState::Flee;
+
+                system_data.stats.routs += 1;
+                system_data.events.push(
+                    EventCategory::Rout,
+                    if is_ally {
+                        "ally unit fled"
+                    } else {
+                        "enemy unit fled"
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Ally>();
+        world.register::<WorldPosition>();
+        world.register::<Destination>();
+        world.register::<Health>();
+        world.register::<UnitState>();
+        world.register::<Morale>();
+
+        world.insert(DeltaTime::new(0.1));
+        world.insert(Terrain::new((400, 50)));
+        world.insert(Statistics::default());
+        world.insert(EventLog::default());
+
+        world
+    }
+
+    fn ally(world: &mut World, x: f64) -> Entity {
+        world
+            .create_entity()
+            .with(Ally)
+            .with(WorldPosition(Point::new(x, 0.0)))
+            .with(Destination(390.0))
+            .with(Health(10.0))
+            .with(UnitState::Walk)
+            .with(Morale::new(0.3, 0.7))
+            .build()
+    }
+
+    fn morale(world: &World, e: Entity) -> f64 {
+        world.read_storage::<Morale>().get(e).unwrap().value
+    }
+
+    fn set_morale(world: &World, e: Entity, value: f64) {
+        world.write_storage::<Morale>().get_mut(e).unwrap().value = value;
+    }
+
+    fn state_is(world: &World, e: Entity, state: UnitState) -> bool {
+        world.read_storage::<UnitState>().get(e) == Some(&state)
+    }
+
+    #[test]
+    fn deaths_nearby_change_the_morale() {
+        let mut world = world();
+        let near = ally(&mut world, 200.0);
+        let far = ally(&mut world, 300.0);
+
+        {
+            let mut events = world.write_resource::<EventLog>();
+            events.unit_died(true, Point::new(210.0, 0.0));
+            events.unit_died(true, Point::new(190.0, 0.0));
+            events.unit_died(false, Point::new(200.0, 0.0));
+        }
+        MoraleSystem.run_now(&world);
+
+        let expected = 1.0 + FRIENDLY_DEATH_MORALE * 2.0 + ENEMY_DEATH_MORALE;
+        assert!((morale(&world, near) - expected).abs() < 1e-9);
+        assert_eq!(morale(&world, far), 1.0);
+
+        // Deaths are only counted once
+        MoraleSystem.run_now(&world);
+        assert!((morale(&world, near) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn enough_friendly_deaths_make_a_unit_flee() {
+        let mut world = world();
+        let e = ally(&mut world, 200.0);
+
+        {
+            let mut events = world.write_resource::<EventLog>();
+            for _ in 0..5 {
+                events.unit_died(true, Point::new(200.0, 0.0));
+            }
+        }
+        MoraleSystem.run_now(&world);
+
+        assert!(state_is(&world, e, UnitState::Flee));
+        assert_eq!(world.read_resource::<Statistics>().routs, 1);
+    }
+
+    #[test]
+    fn flee_and_recover_thresholds_differ() {
+        let mut world = world();
+        let e = ally(&mut world, 200.0);
+
+        set_morale(&world, e, 0.25);
+        MoraleSystem.run_now(&world);
+        assert!(state_is(&world, e, UnitState::Flee));
+
+        // Above the flee threshold but not recovered yet
+        set_morale(&world, e, 0.5);
+        MoraleSystem.run_now(&world);
+        assert!(state_is(&world, e, UnitState::Flee));
+
+        set_morale(&world, e, 0.75);
+        MoraleSystem.run_now(&world);
+        assert!(state_is(&world, e, UnitState::Walk));
+
+        // Dropping below the recover threshold doesn't make it flee again
+        set_morale(&world, e, 0.5);
+        MoraleSystem.run_now(&world);
+        assert!(state_is(&world, e, UnitState::Walk));
+        assert_eq!(world.read_resource::<Statistics>().routs, 1);
+    }
+}
@@ -0,0 +1,121 @@
+use minifb::Scale;
+
+use crate::difficulty::Difficulty;
+use crate::level::LEVEL_COUNT;
+
+pub const USAGE: &str = "Usage: castle-game [OPTIONS]
+
+Options:
+    --level <number>        The level to play, defaults to 1
+    --difficulty <name>     easy, normal or hard, defaults to normal
+    --scale <1|2|4|8>       The size of a pixel on the screen, defaults to 2
+    --help                  Print this message";
+
+// The options that take a value
+const OPTIONS: [&str; 3] = ["--level", "--difficulty", "--scale"];
+
+/// Settings passed on the command line for a single run of the game.
+#[derive(Debug, Copy, Clone)]
+pub struct Options {
+    pub level: u8,
+    pub difficulty: Difficulty,
+    pub scale: Scale,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            level: 1,
+            difficulty: Difficulty::default(),
+            scale: Scale::X2,
+        }
+    }
+}
+
+impl Options {
+    /// Parse the arguments, without the name of the executable.
+    ///
+    /// `Ok(None)` is returned when the usage was requested.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Self>, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Ok(None);
+            }
+            if !OPTIONS.contains(&arg.as_str()) {
+                return Err(format!("unknown option \"{}\"", arg));
+            }
+
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for \"{}\"", arg))?;
+            match arg.as_str() {
+                "--level" => {
+                    options.level = match value.parse() {
+                        Ok(level) if (1..=LEVEL_COUNT).contains(&level) => level,
+                        _ => return Err(format!("invalid level \"{}\"", value)),
+                    }
+                }
+                "--difficulty" => options.difficulty = value.parse()?,
+                "--scale" => {
+                    options.scale = match value.as_str() {
+                        "1" => Scale::X1,
+                        "2" => Scale::X2,
+                        "4" => Scale::X4,
+                        "8" => Scale::X8,
+                        _ => return Err(format!("invalid scale \"{}\"", value)),
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(Some(options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Options>, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn valid_options_are_parsed() {
+        let options = parse(&[]).unwrap().unwrap();
+        assert_eq!(options.level, 1);
+        assert_eq!(options.difficulty, Difficulty::Normal);
+        assert!(matches!(options.scale, Scale::X2));
+
+        let options = parse(&["--difficulty", "hard", "--scale", "4", "--level", "1"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.level, 1);
+        assert_eq!(options.difficulty, Difficulty::Hard);
+        assert!(matches!(options.scale, Scale::X4));
+
+        assert!(parse(&["--level", "1", "--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_options_are_reported() {
+        assert_eq!(
+            parse(&["--speed", "2"]).unwrap_err(),
+            "unknown option \"--speed\""
+        );
+        assert_eq!(
+            parse(&["--speed"]).unwrap_err(),
+            "unknown option \"--speed\""
+        );
+        assert_eq!(
+            parse(&["--level"]).unwrap_err(),
+            "missing value for \"--level\""
+        );
+        assert_eq!(parse(&["--level", "0"]).unwrap_err(), "invalid level \"0\"");
+        assert_eq!(parse(&["--scale", "3"]).unwrap_err(), "invalid scale \"3\"");
+        assert!(parse(&["--difficulty", "impossible"]).is_err());
+    }
+}
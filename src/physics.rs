@@ -14,6 +14,10 @@ impl Velocity {
     pub fn new(x: f64, y: f64) -> Self {
         Velocity { x, y }
     }
+
+    pub fn length(self) -> f64 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
 }
 
 #[derive(Default)]
@@ -10,6 +10,29 @@ use specs_derive::Component;
 use super::*;
 
 const BLOOD_COLOR: u32 = 0xAC_32_33;
+const SPLIT_PUFF_COLOR: u32 = 0xCB_DB_FC;
+const SPLIT_PUFF_PARTICLES: usize = 8;
+const IMPACT_COLOR: u32 = 0xFF_3F_28_1E;
+
+// Seconds before the marks of an arrow hitting the ground have faded away
+const IMPACT_DECAL_LIFETIME: f64 = 10.0;
+// Different marks an arrow can leave where it hits the ground, one is picked at random
+const IMPACT_DECALS: [&[((i32, i32), u32)]; 3] = [
+    &[((0, 0), IMPACT_COLOR), ((-1, 0), IMPACT_COLOR)],
+    &[
+        ((0, 0), IMPACT_COLOR),
+        ((1, 0), IMPACT_COLOR),
+        ((0, 1), IMPACT_COLOR),
+    ],
+    &[
+        ((-1, 0), IMPACT_COLOR),
+        ((1, 0), IMPACT_COLOR),
+        ((0, 1), IMPACT_COLOR),
+    ],
+];
+
+// Maximum amount of positions a trail can remember
+const MAX_TRAIL_LENGTH: usize = 16;
 
 #[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum IgnoreCollision {
@@ -32,6 +55,216 @@ pub struct Arrow(pub f64);
 #[derive(Component, Debug, Copy, Clone)]
 pub struct Damage(pub f64);
 
+/// The entity that fired the projectile, it's credited with the kills.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Owner(pub Entity);
+
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DamageType {
+    Pierce,
+    Crush,
+    Blast,
+}
+
+#[derive(Component, Debug, Copy, Clone)]
+pub struct GravityScale(pub f64);
+
+/// Rate per second at which the velocity decays because of air resistance.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Drag(pub f64);
+
+/// Hard limit on the speed of a projectile, applied after gravity and drag.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct MaxSpeed(pub f64);
+
+/// Lets a projectile skip off the terrain when it hits it at a shallow angle.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Ricochet {
+    pub max_angle: f64,
+    pub min_speed: f64,
+    pub restitution: f64,
+    pub bounces_left: u8,
+}
+
+impl Ricochet {
+    pub fn new(max_angle_degrees: f64, min_speed: f64, restitution: f64, bounces: u8) -> Self {
+        Ricochet {
+            max_angle: max_angle_degrees.to_radians(),
+            min_speed,
+            restitution,
+            bounces_left: bounces,
+        }
+    }
+
+    /// Reflect the velocity on the surface with the normal, returns whether it bounced.
+    pub fn try_bounce(&mut self, vel: &mut Velocity, normal: (f64, f64)) -> bool {
+        let speed = vel.length();
+        if self.bounces_left == 0 || speed < self.min_speed {
+            return false;
+        }
+
+        // The normal points away from the terrain so the dot product is negative when going in
+        let dot = vel.x * normal.0 + vel.y * normal.1;
+        if dot >= 0.0 || (-dot / speed).asin() > self.max_angle {
+            return false;
+        }
+
+        vel.x = (vel.x - 2.0 * dot * normal.0) * self.restitution;
+        vel.y = (vel.y - 2.0 * dot * normal.1) * self.restitution;
+        self.bounces_left -= 1;
+
+        true
+    }
+}
+
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Trail {
+    pub color: u32,
+    pub length: usize,
+    pub interval: f64,
+
+    // Positions can be outside of the screen, they are clipped when drawn
+    points: [(i32, i32); MAX_TRAIL_LENGTH],
+    // Amount of valid points in the buffer
+    count: usize,
+    // Index where the next point will be written
+    head: usize,
+    time_left: f64,
+}
+
+impl Trail {
+    pub fn new(length: usize, interval: f64, color: u32) -> Self {
+        Trail {
+            color,
+            length: length.clamp(1, MAX_TRAIL_LENGTH),
+            interval,
+
+            points: [(0, 0); MAX_TRAIL_LENGTH],
+            count: 0,
+            head: 0,
+            time_left: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, pos: (i32, i32), dt: f64) {
+        self.time_left -= dt;
+        if self.time_left > 0.0 {
+            return;
+        }
+        self.time_left += self.interval;
+
+        self.points[self.head] = pos;
+        self.head = (self.head + 1) % self.length;
+        self.count = (self.count + 1).min(self.length);
+    }
+
+    pub fn clear(&mut self) {
+        self.count = 0;
+    }
+
+    /// The remembered positions from newest to oldest.
+    pub fn points(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (1..=self.count).map(move |i| self.points[(self.head + self.length - i) % self.length])
+    }
+}
+
+#[derive(SystemData)]
+pub struct ProjectileComponents<'a> {
+    pub dmg: ReadStorage<'a, Damage>,
+    pub damage_type: ReadStorage<'a, DamageType>,
+    pub mask: ReadStorage<'a, MaskId>,
+    pub arrow: ReadStorage<'a, Arrow>,
+    pub line: ReadStorage<'a, Line>,
+    pub ignore: ReadStorage<'a, IgnoreCollision>,
+    pub trail: ReadStorage<'a, Trail>,
+    pub ricochet: ReadStorage<'a, Ricochet>,
+    pub gravity_scale: ReadStorage<'a, GravityScale>,
+    pub drag: ReadStorage<'a, Drag>,
+    pub max_speed: ReadStorage<'a, MaxSpeed>,
+    pub explosion: ReadStorage<'a, Explosion>,
+    pub garrison_shot: ReadStorage<'a, GarrisonShot>,
+    pub effect_on_hit: ReadStorage<'a, StatusEffectOnHit>,
+    pub owner: ReadStorage<'a, Owner>,
+}
+
+/// Give the new projectile the components of the entity it's fired from, with the damage
+/// multiplied by the factor. The sprite and splitting are left to the caller.
+pub fn copy_projectile_components(
+    from: Entity,
+    to: Entity,
+    damage_factor: f64,
+    components: &ProjectileComponents,
+    updater: &LazyUpdate,
+) {
+    if let Some(dmg) = components.dmg.get(from) {
+        updater.insert(to, Damage(dmg.0 * damage_factor));
+    }
+    if let Some(explosion) = components.explosion.get(from) {
+        let mut explosion = *explosion;
+        explosion.damage *= damage_factor;
+        updater.insert(to, explosion);
+    }
+    if let Some(trail) = components.trail.get(from) {
+        let mut trail = *trail;
+        trail.clear();
+        updater.insert(to, trail);
+    }
+    if let Some(damage_type) = components.damage_type.get(from) {
+        updater.insert(to, *damage_type);
+    }
+    if let Some(mask) = components.mask.get(from) {
+        updater.insert(to, *mask);
+    }
+    if let Some(arrow) = components.arrow.get(from) {
+        updater.insert(to, *arrow);
+    }
+    if let Some(line) = components.line.get(from) {
+        updater.insert(to, *line);
+    }
+    if let Some(ignore) = components.ignore.get(from) {
+        updater.insert(to, *ignore);
+    }
+    if let Some(ricochet) = components.ricochet.get(from) {
+        updater.insert(to, *ricochet);
+    }
+    if let Some(gravity_scale) = components.gravity_scale.get(from) {
+        updater.insert(to, *gravity_scale);
+    }
+    if let Some(drag) = components.drag.get(from) {
+        updater.insert(to, *drag);
+    }
+    if let Some(max_speed) = components.max_speed.get(from) {
+        updater.insert(to, *max_speed);
+    }
+    if let Some(garrison_shot) = components.garrison_shot.get(from) {
+        updater.insert(to, *garrison_shot);
+    }
+    if let Some(effect_on_hit) = components.effect_on_hit.get(from) {
+        updater.insert(to, *effect_on_hit);
+    }
+    if let Some(owner) = components.owner.get(from) {
+        updater.insert(to, *owner);
+    }
+}
+
+pub struct TrailSystem;
+impl<'a> System<'a> for TrailSystem {
+    type SystemData = (
+        Read<'a, DeltaTime>,
+        ReadStorage<'a, Projectile>,
+        ReadStorage<'a, WorldPosition>,
+        WriteStorage<'a, Trail>,
+    );
+
+    fn run(&mut self, (dt, proj, pos, mut trail): Self::SystemData) {
+        let dt = dt.to_seconds();
+
+        for (_, pos, trail) in (&proj, &pos, &mut trail).join() {
+            trail.update(pos.0.as_i32(), dt);
+        }
+    }
+}
+
 pub struct ArrowSystem;
 impl<'a> System<'a> for ArrowSystem {
     type SystemData = (
@@ -43,7 +276,7 @@ impl<'a> System<'a> for ArrowSystem {
 
     fn run(&mut self, (pos, vel, mut arrow, mut line): Self::SystemData) {
         for (pos, vel, arrow, line) in (&pos, &vel, &mut arrow, &mut line).join() {
-            let rot = (vel.y as f64).atan2(vel.x as f64);
+            let rot = vel.y.atan2(vel.x);
 
             line.p1.x = pos.0.x as usize;
             line.p1.y = pos.0.y as usize;
@@ -54,6 +287,103 @@ impl<'a> System<'a> for ArrowSystem {
     }
 }
 
+// The built-in projectiles don't use a fuse
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SplitTrigger {
+    Apex,
+    Fuse(f64),
+    // The children bounce off the terrain
+    Impact,
+}
+
+/// Split a projectile mid-air into copies of itself that share its damage.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Split {
+    pub trigger: SplitTrigger,
+    pub count: usize,
+    pub spread: f64,
+    pub inherit_velocity: f64,
+
+    time_alive: f64,
+    // The vertical velocity in the previous frame, used to detect the apex
+    previous_vel_y: Option<f64>,
+    // Whether the projectile hit the terrain, used by the impact trigger
+    impacted: bool,
+}
+
+impl Split {
+    pub fn new(trigger: SplitTrigger, count: usize, spread: f64, inherit_velocity: f64) -> Self {
+        Split {
+            trigger,
+            count,
+            spread,
+            inherit_velocity,
+            time_alive: 0.0,
+            previous_vel_y: None,
+            impacted: false,
+        }
+    }
+
+    /// Bounce a projectile with the impact trigger off the terrain, false for other triggers.
+    pub fn hit_terrain(&mut self, vel: &mut Velocity, normal: Option<(f64, f64)>) -> bool {
+        if self.trigger != SplitTrigger::Impact {
+            return false;
+        }
+
+        match normal {
+            Some(normal) => {
+                let dot = vel.x * normal.0 + vel.y * normal.1;
+                vel.x -= 2.0 * dot * normal.0;
+                vel.y -= 2.0 * dot * normal.1;
+            }
+            // Without a surface the children go back up
+            None => vel.y = -vel.y,
+        }
+        self.impacted = true;
+
+        true
+    }
+
+    fn should_split(&mut self, vel: Velocity, dt: f64) -> bool {
+        self.time_alive += dt;
+        let previous_vel_y = self.previous_vel_y.replace(vel.y);
+
+        match self.trigger {
+            // The y axis points down so the projectile is rising while the velocity is negative
+            SplitTrigger::Apex => matches!(previous_vel_y, Some(y) if y < 0.0 && vel.y >= 0.0),
+            SplitTrigger::Fuse(time) => self.time_alive >= time,
+            SplitTrigger::Impact => self.impacted,
+        }
+    }
+}
+
+// Apply the gravity, the drag and the speed limit of a projectile for a single step
+fn integrate_velocity(
+    vel: &mut Velocity,
+    grav: f64,
+    gravity_scale: Option<&GravityScale>,
+    drag: Option<&Drag>,
+    max_speed: Option<&MaxSpeed>,
+    dt: f64,
+) {
+    vel.y += grav * gravity_scale.map_or(1.0, |s| s.0) * dt;
+
+    if let Some(drag) = drag {
+        let factor = (-drag.0 * dt).exp();
+        vel.x *= factor;
+        vel.y *= factor;
+    }
+
+    if let Some(max_speed) = max_speed {
+        let speed = vel.length();
+        if speed > max_speed.0 {
+            vel.x *= max_speed.0 / speed;
+            vel.y *= max_speed.0 / speed;
+        }
+    }
+}
+
 #[derive(SystemData)]
 pub struct ProjectileSystemData<'a> {
     entities: Entities<'a>,
@@ -63,6 +393,14 @@ pub struct ProjectileSystemData<'a> {
     audio: Read<'a, Audio>,
     proj: ReadStorage<'a, Projectile>,
     mask: ReadStorage<'a, MaskId>,
+    gravity_scale: ReadStorage<'a, GravityScale>,
+    drag: ReadStorage<'a, Drag>,
+    max_speed: ReadStorage<'a, MaxSpeed>,
+    explosion: ReadStorage<'a, Explosion>,
+    effect_on_hit: ReadStorage<'a, StatusEffectOnHit>,
+    ignore: ReadStorage<'a, IgnoreCollision>,
+    ricochet: WriteStorage<'a, Ricochet>,
+    split: WriteStorage<'a, Split>,
     line: WriteStorage<'a, Line>,
     vel: WriteStorage<'a, Velocity>,
     pos: WriteStorage<'a, WorldPosition>,
@@ -97,6 +435,15 @@ impl<'a> System<'a> for ProjectileSystem {
                         continue;
                     }
 
+                    if let Some(ricochet) = system_data.ricochet.get_mut(entity) {
+                        // Skip off the terrain, the position stays in front of the surface
+                        if let Some(normal) = system_data.terrain.normal_at(point) {
+                            if ricochet.try_bounce(vel, normal) {
+                                continue;
+                            }
+                        }
+                    }
+
                     if let Some(mask) = system_data.mask.get(entity) {
                         // Create a crater if there is a mask for it
                         system_data.updater.insert(
@@ -108,6 +455,31 @@ impl<'a> System<'a> for ProjectileSystem {
                         system_data.audio.play_heavy_projectile();
                     }
 
+                    if let Some(explosion) = system_data.explosion.get(entity) {
+                        // Damage the units around the impact, the last position is used as the
+                        // center because the collision point itself is inside the terrain
+                        system_data.updater.insert(
+                            system_data.entities.create(),
+                            AreaDamage {
+                                center: pos.0,
+                                explosion: *explosion,
+                                ignore: system_data.ignore.get(entity).copied(),
+                                effect: system_data
+                                    .effect_on_hit
+                                    .get(entity)
+                                    .map(|effect_on_hit| effect_on_hit.0),
+                            },
+                        );
+                    }
+
+                    if let Some(split) = system_data.split.get_mut(entity) {
+                        // The split system spawns the children and removes the projectile, it
+                        // stays in front of the surface until then
+                        if split.hit_terrain(vel, system_data.terrain.normal_at(point)) {
+                            continue;
+                        }
+                    }
+
                     if let Some(line) = system_data.line.get(entity) {
                         // Keep drawing the line if there is one, this makes the arrows stay stuck
                         // in the ground
@@ -127,6 +499,17 @@ impl<'a> System<'a> for ProjectileSystem {
                                 .insert(system_data.entities.create(), line_copy);
                         }
 
+                        // Leave a random mark on the ground
+                        let decal = IMPACT_DECALS
+                            [Uniform::new(0, IMPACT_DECALS.len()).sample(&mut rand::thread_rng())];
+                        system_data.updater.exec_mut(move |world| {
+                            world.write_resource::<Terrain>().stamp_fading_decal(
+                                point,
+                                decal,
+                                IMPACT_DECAL_LIFETIME,
+                            );
+                        });
+
                         // Play a sound
                         system_data.audio.play_light_projectile();
                     }
@@ -135,13 +518,113 @@ impl<'a> System<'a> for ProjectileSystem {
                 }
                 None => {
                     pos.0 = next;
-                    vel.y += grav * dt;
+
+                    integrate_velocity(
+                        vel,
+                        grav,
+                        system_data.gravity_scale.get(entity),
+                        system_data.drag.get(entity),
+                        system_data.max_speed.get(entity),
+                        dt,
+                    );
                 }
             }
         }
     }
 }
 
+#[derive(SystemData)]
+pub struct SplitSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    proj: ReadStorage<'a, Projectile>,
+    vel: ReadStorage<'a, Velocity>,
+    pos: ReadStorage<'a, WorldPosition>,
+    bb: ReadStorage<'a, ProjectileBoundingBox>,
+    sprite: ReadStorage<'a, Sprite>,
+    projectile: ProjectileComponents<'a>,
+    split: WriteStorage<'a, Split>,
+    stats: Write<'a, Statistics>,
+    updater: Read<'a, LazyUpdate>,
+}
+
+pub struct SplitSystem;
+impl<'a> System<'a> for SplitSystem {
+    type SystemData = SplitSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let mut rng = rand::thread_rng();
+
+        for (entity, _, vel, pos, split) in (
+            &*system_data.entities,
+            &system_data.proj,
+            &system_data.vel,
+            &system_data.pos,
+            &mut system_data.split,
+        )
+            .join()
+        {
+            if !split.should_split(*vel, dt) {
+                continue;
+            }
+
+            let base = Velocity::new(
+                vel.x * split.inherit_velocity,
+                vel.y * split.inherit_velocity,
+            );
+            let velocities = volley_velocities(
+                base,
+                split.count,
+                split.spread,
+                VolleyPattern::Even,
+                &mut rng,
+            );
+            let damage_factor = 1.0 / velocities.len() as f64;
+
+            // The children are copies of the parent without the ability to split again
+            for vel in velocities.iter() {
+                let child = system_data.entities.create();
+                system_data.updater.insert(child, Projectile);
+                system_data.updater.insert(child, *pos);
+                system_data.updater.insert(child, *vel);
+                let entity_bb: Option<&ProjectileBoundingBox> = system_data.bb.get(entity);
+                if let Some(bb_e) = entity_bb {
+                    system_data.updater.insert(child, *bb_e);
+                }
+                let entity_sprite: Option<&Sprite> = system_data.sprite.get(entity);
+                if let Some(sprite_e) = entity_sprite {
+                    system_data.updater.insert(child, *sprite_e);
+                }
+                copy_projectile_components(
+                    entity,
+                    child,
+                    damage_factor,
+                    &system_data.projectile,
+                    &system_data.updater,
+                );
+            }
+
+            // Hide the parent disappearing with a small puff
+            let between = Uniform::new(-20.0, 20.0);
+            for _ in 0..SPLIT_PUFF_PARTICLES {
+                let puff = system_data.entities.create();
+                system_data
+                    .updater
+                    .insert(puff, PixelParticle::new(SPLIT_PUFF_COLOR, 0.5));
+                system_data.updater.insert(puff, *pos);
+                system_data.updater.insert(
+                    puff,
+                    Velocity::new(between.sample(&mut rng), between.sample(&mut rng)),
+                );
+            }
+
+            let _ = system_data.entities.delete(entity);
+            system_data.stats.projectiles_fired += velocities.len() - 1;
+        }
+    }
+}
+
 #[derive(SystemData)]
 pub struct ProjectileCollisionSystemData<'a> {
     entities: Entities<'a>,
@@ -152,10 +635,19 @@ pub struct ProjectileCollisionSystemData<'a> {
     proj_bb: ReadStorage<'a, ProjectileBoundingBox>,
     bb: ReadStorage<'a, BoundingBox>,
     dmg: ReadStorage<'a, Damage>,
+    damage_type: ReadStorage<'a, DamageType>,
+    garrison_shot: ReadStorage<'a, GarrisonShot>,
+    effect_on_hit: ReadStorage<'a, StatusEffectOnHit>,
+    owner: ReadStorage<'a, Owner>,
+    resistance: ReadStorage<'a, Resistance>,
+    effects: WriteStorage<'a, StatusEffects>,
+    veterancy: WriteStorage<'a, Veterancy>,
     ignore: ReadStorage<'a, IgnoreCollision>,
     ally: ReadStorage<'a, Ally>,
     enemy: ReadStorage<'a, Enemy>,
     health: WriteStorage<'a, Health>,
+    stats: Write<'a, Statistics>,
+    events: Write<'a, EventLog>,
 }
 
 pub struct ProjectileCollisionSystem;
@@ -200,16 +692,49 @@ impl<'a> System<'a> for ProjectileCollisionSystem {
                 // When there is a collision with a unit
                 let target_aabb = *target_bb + *target_pos.0;
                 if proj_aabb.intersects(&*target_aabb) {
-                    if reduce_unit_health(&system_data.entities, target, target_health, proj_dmg.0)
-                    {
-                        // The ally died
-                        system_data.updater.insert(
-                            system_data.entities.create(),
-                            FloatingText {
-                                text: "x".to_string(),
-                                pos: target_pos.0,
-                                time_alive: 2.0,
-                            },
+                    let invulnerable = matches!(
+                        system_data.effects.get(target),
+                        Some(effects) if effects.is_invulnerable()
+                    );
+                    let dmg = if invulnerable {
+                        0.0
+                    } else {
+                        resisted_damage(
+                            system_data.resistance.get(target),
+                            system_data.damage_type.get(proj).copied(),
+                            proj_dmg.0,
+                        )
+                    };
+                    let is_ally = system_data.ally.get(target).is_some();
+                    let died = reduce_unit_health(
+                        &system_data.entities,
+                        &mut system_data.stats,
+                        target,
+                        is_ally,
+                        target_health,
+                        dmg,
+                    );
+                    if let (Some(effect_on_hit), Some(effects), false) = (
+                        system_data.effect_on_hit.get(proj),
+                        system_data.effects.get_mut(target),
+                        died || invulnerable,
+                    ) {
+                        effects.apply(effect_on_hit.0);
+                    }
+                    if died && system_data.garrison_shot.get(proj).is_some() {
+                        system_data.stats.garrison_kills += 1;
+                    }
+                    if died {
+                        // The unit died
+                        credit_kill(
+                            &mut system_data.veterancy,
+                            system_data.owner.get(proj).map(|owner| owner.0),
+                        );
+                        system_data.events.unit_died(is_ally, target_pos.0);
+                        spawn_death_effects(
+                            &system_data.entities,
+                            &system_data.updater,
+                            *target_pos,
                         );
                     }
 
@@ -247,13 +772,13 @@ impl<'a> System<'a> for ProjectileRemovalFromMaskSystem {
 
     fn run(&mut self, (entities, mask, line): Self::SystemData) {
         for mask in mask.join() {
-            let sx = (mask.size.0 / 2) as usize;
-            let sy = (mask.size.1 / 2) as usize;
+            let sx = mask.size.0 / 2;
+            let sy = mask.size.1 / 2;
 
             for (entity, line) in (&*entities, &line).join() {
                 // Check if the line's start point is inside the mask and remove it if that's the case
-                let dx = (mask.pos.0 - line.p1.x as i32).abs() as usize;
-                let dy = (mask.pos.1 - line.p1.y as i32).abs() as usize;
+                let dx = (mask.pos.0 - line.p1.x as i32).unsigned_abs() as usize;
+                let dy = (mask.pos.1 - line.p1.y as i32).unsigned_abs() as usize;
                 if dx <= sx && dy <= sy {
                     let _ = entities.delete(entity);
                 }
@@ -261,3 +786,179 @@ impl<'a> System<'a> for ProjectileRemovalFromMaskSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+        world.insert(DeltaTime::new(0.1));
+        world.insert(Statistics::default());
+
+        world
+    }
+
+    // Horizontal distance traveled until the projectile is back at the height it was fired from
+    fn range(vel: Velocity, gravity_scale: Option<&GravityScale>, drag: Option<&Drag>) -> f64 {
+        let dt = 0.0001;
+        let (mut vel, mut x, mut y) = (vel, 0.0, 0.0);
+        loop {
+            x += vel.x * dt;
+            y += vel.y * dt;
+            if y > 0.0 {
+                return x;
+            }
+            integrate_velocity(&mut vel, 98.1, gravity_scale, drag, None, dt);
+        }
+    }
+
+    #[test]
+    fn lower_gravity_scale_shoots_further() {
+        let vel = Velocity::new(50.0, -50.0);
+        // Without drag the range is 2 * vx * vy / (gravity * scale)
+        let full = range(vel, None, None);
+        let half = range(vel, Some(&GravityScale(0.5)), None);
+        assert!((full - 2.0 * 50.0 * 50.0 / 98.1).abs() < 0.1);
+        assert!((half - 2.0 * full).abs() < 0.1);
+
+        assert!(range(vel, None, Some(&Drag(0.1))) < full);
+    }
+
+    #[test]
+    fn speed_is_clamped_after_integration() {
+        let mut vel = Velocity::new(30.0, 40.0);
+        integrate_velocity(&mut vel, 98.1, None, None, Some(&MaxSpeed(25.0)), 0.1);
+        assert!((vel.length() - 25.0).abs() < 1e-9);
+
+        // Slower projectiles are left alone
+        let mut vel = Velocity::new(3.0, 4.0);
+        integrate_velocity(
+            &mut vel,
+            10.0,
+            Some(&GravityScale(0.0)),
+            None,
+            Some(&MaxSpeed(25.0)),
+            0.1,
+        );
+        assert_eq!((vel.x, vel.y), (3.0, 4.0));
+    }
+
+    #[test]
+    fn trail_samples_at_the_interval_and_wraps_around() {
+        let mut trail = Trail::new(3, 0.5, 0);
+        // The first position is taken right away, the next ones only after the interval
+        trail.update((0, 0), 0.0);
+        trail.update((1, 0), 0.25);
+        trail.update((2, 0), 0.25);
+        assert_eq!(trail.points().collect::<Vec<_>>(), [(2, 0), (0, 0)]);
+
+        // Older positions are overwritten once the trail is full
+        for x in 3..7 {
+            trail.update((x, 0), 0.5);
+        }
+        assert_eq!(trail.points().collect::<Vec<_>>(), [(6, 0), (5, 0), (4, 0)]);
+
+        trail.clear();
+        assert_eq!(trail.points().count(), 0);
+        trail.update((7, 0), 0.5);
+        assert_eq!(trail.points().collect::<Vec<_>>(), [(7, 0)]);
+    }
+
+    #[test]
+    fn trail_length_is_clamped() {
+        assert_eq!(Trail::new(0, 0.1, 0).length, 1);
+        assert_eq!(Trail::new(1000, 0.1, 0).length, MAX_TRAIL_LENGTH);
+    }
+
+    #[test]
+    fn ricochet_bounces_at_shallow_angles_until_out_of_bounces() {
+        let mut ricochet = Ricochet::new(15.0, 10.0, 0.5, 2);
+        let up = (0.0, -1.0);
+
+        // Hitting the ground at 10 degrees keeps the angle but loses half of the speed
+        let angle = 10.0f64.to_radians();
+        let mut vel = Velocity::new(100.0 * angle.cos(), 100.0 * angle.sin());
+        assert!(ricochet.try_bounce(&mut vel, up));
+        assert!((vel.length() - 50.0).abs() < 1e-9);
+        assert!((vel.x - 50.0 * angle.cos()).abs() < 1e-9);
+        assert!((vel.y + 50.0 * angle.sin()).abs() < 1e-9);
+        assert_eq!(ricochet.bounces_left, 1);
+
+        // Going away from the surface never bounces
+        assert!(!ricochet.try_bounce(&mut vel, up));
+        assert_eq!(ricochet.bounces_left, 1);
+
+        let mut vel = Velocity::new(100.0 * angle.cos(), 100.0 * angle.sin());
+        assert!(ricochet.try_bounce(&mut vel, up));
+        assert_eq!(ricochet.bounces_left, 0);
+
+        let mut vel = Velocity::new(100.0 * angle.cos(), 100.0 * angle.sin());
+        assert!(!ricochet.try_bounce(&mut vel, up));
+        assert_eq!((vel.x, vel.y), (100.0 * angle.cos(), 100.0 * angle.sin()));
+    }
+
+    #[test]
+    fn ricochet_stops_at_steep_angles_and_low_speeds() {
+        let mut ricochet = Ricochet::new(15.0, 10.0, 0.5, 1);
+        let up = (0.0, -1.0);
+
+        let angle = 20.0f64.to_radians();
+        let mut vel = Velocity::new(100.0 * angle.cos(), 100.0 * angle.sin());
+        assert!(!ricochet.try_bounce(&mut vel, up));
+
+        let angle = 14.0f64.to_radians();
+        let mut vel = Velocity::new(9.0 * angle.cos(), 9.0 * angle.sin());
+        assert!(!ricochet.try_bounce(&mut vel, up));
+
+        assert_eq!(ricochet.bounces_left, 1);
+    }
+
+    #[test]
+    fn split_spawns_slower_children_at_the_apex() {
+        let mut world = world();
+        let parent = world
+            .create_entity()
+            .with(Projectile)
+            .with(WorldPosition(Point::new(100.0, 100.0)))
+            .with(Velocity::new(20.0, -1.0))
+            .with(Damage(30.0))
+            .with(Split::new(SplitTrigger::Apex, 3, 0.6, 0.5))
+            .build();
+        world.write_resource::<Statistics>().projectiles_fired = 1;
+
+        // Still rising
+        SplitSystem.run_now(&world);
+        world.maintain();
+        assert!(world.is_alive(parent));
+
+        world
+            .write_storage::<Velocity>()
+            .insert(parent, Velocity::new(20.0, 0.0))
+            .unwrap();
+        SplitSystem.run_now(&world);
+        world.maintain();
+        assert!(!world.is_alive(parent));
+
+        let projectiles = world.read_storage::<Projectile>();
+        let vel = world.read_storage::<Velocity>();
+        let dmg = world.read_storage::<Damage>();
+        let split = world.read_storage::<Split>();
+        let children: Vec<(Velocity, f64)> = (&projectiles, &vel, &dmg)
+            .join()
+            .map(|(_, vel, dmg)| (*vel, dmg.0))
+            .collect();
+        assert_eq!(children.len(), 3);
+        for (vel, dmg) in children {
+            // Half of the speed of the parent with the small variation of a volley
+            assert!((vel.length() - 10.0).abs() <= 10.0 * 0.05 + 1e-9);
+            assert!((dmg - 10.0).abs() < 1e-9);
+        }
+        // The children don't split again
+        assert_eq!(split.join().count(), 0);
+
+        // The parent was already counted when it was fired
+        assert_eq!(world.read_resource::<Statistics>().projectiles_fired, 3);
+    }
+}
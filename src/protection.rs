@@ -0,0 +1,223 @@
+use specs::prelude::*;
+
+use super::*;
+
+// Damage per second dealt to units inside the protection zone of the other side
+const AURA_DAMAGE: f64 = 4.0;
+// Health per second restored to units inside their own protection zone
+const ZONE_REGEN: f64 = 1.0;
+/// Multiplier of the damage of projectiles fired from inside the protection zone of the other side.
+pub const PROTECTED_PROJECTILE_DAMAGE: f64 = 0.25;
+
+pub const ZONE_OUTLINE_COLOR: u32 = 0xFF_8A_9B_A8;
+// Length in pixels of the dashes of the outline and the gaps between them
+pub const ZONE_OUTLINE_DASH: usize = 4;
+
+/// The horizontal range in front of a castle where its units spawn.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ProtectionZone {
+    pub min_x: f64,
+    pub max_x: f64,
+}
+
+impl ProtectionZone {
+    pub fn new(min_x: f64, max_x: f64) -> Self {
+        ProtectionZone { min_x, max_x }
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        x >= self.min_x && x < self.max_x
+    }
+}
+
+/// The protection zones of both sides, the default has empty zones.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ProtectionZones {
+    pub ally: ProtectionZone,
+    pub enemy: ProtectionZone,
+}
+
+impl ProtectionZones {
+    /// The zone of the other side, the one a unit of this side shouldn't enter.
+    pub fn opposing(&self, is_ally: bool) -> ProtectionZone {
+        if is_ally {
+            self.enemy
+        } else {
+            self.ally
+        }
+    }
+
+    pub fn own(&self, is_ally: bool) -> ProtectionZone {
+        if is_ally {
+            self.ally
+        } else {
+            self.enemy
+        }
+    }
+}
+
+#[derive(SystemData)]
+pub struct ProtectionSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    zones: Read<'a, ProtectionZones>,
+    ally: ReadStorage<'a, Ally>,
+    pos: ReadStorage<'a, WorldPosition>,
+    effects: WriteStorage<'a, StatusEffects>,
+}
+
+pub struct ProtectionSystem;
+impl<'a> System<'a> for ProtectionSystem {
+    type SystemData = ProtectionSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+
+        for (e, pos, effects) in (
+            &*system_data.entities,
+            &system_data.pos,
+            &mut system_data.effects,
+        )
+            .join()
+        {
+            let is_ally = system_data.ally.get(e).is_some();
+
+            if system_data.zones.own(is_ally).contains(pos.0.x) {
+                // Units recover in front of their own castle
+                effects.apply(StatusEffect::regen(ZONE_REGEN, dt));
+            } else {
+                // The spawn protection is lost when leaving the own zone
+                effects.remove(StatusEffectKind::Invulnerable);
+            }
+
+            // Hurt the units that are camping in front of the castle of the other side, the aura
+            // only lasts for this frame so it stops as soon as the unit leaves the zone
+            if system_data.zones.opposing(is_ally).contains(pos.0.x) {
+                effects.apply(StatusEffect::new(StatusEffectKind::Aura, AURA_DAMAGE, dt));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+        world.insert(DeltaTime::new(0.1));
+        world.insert(Gravity(98.1));
+        world.insert(Terrain::new((200, 100)));
+        world.insert(Statistics::default());
+        world.insert(ProtectionZones {
+            ally: ProtectionZone::new(0.0, 50.0),
+            enemy: ProtectionZone::new(150.0, 200.0),
+        });
+
+        world
+    }
+
+    fn ally(world: &mut World, x: f64) -> Entity {
+        world
+            .create_entity()
+            .with(Ally)
+            .with(WorldPosition(Point::new(x, 10.0)))
+            .with(
+                StatusEffects::default()
+                    .with_effect(StatusEffect::timed(StatusEffectKind::Invulnerable, 5.0)),
+            )
+            .build()
+    }
+
+    #[test]
+    fn zone_includes_the_start_and_excludes_the_end() {
+        let zone = ProtectionZone::new(150.0, 200.0);
+        assert!(!zone.contains(149.9));
+        assert!(zone.contains(150.0));
+        assert!(zone.contains(199.9));
+        assert!(!zone.contains(200.0));
+    }
+
+    #[test]
+    fn aura_only_hurts_units_in_the_opposing_zone() {
+        let mut world = world();
+        let home = ally(&mut world, 10.0);
+        let field = ally(&mut world, 100.0);
+        let camping = ally(&mut world, 160.0);
+        ProtectionSystem.run_now(&world);
+
+        let effects = world.read_storage::<StatusEffects>();
+        let aura = |entity| {
+            effects
+                .get(entity)
+                .unwrap()
+                .get(StatusEffectKind::Aura)
+                .map(|effect| effect.magnitude)
+        };
+        assert_eq!(aura(home), None);
+        assert_eq!(aura(field), None);
+        assert_eq!(aura(camping), Some(AURA_DAMAGE));
+
+        // Only the own zone heals
+        assert!(effects
+            .get(home)
+            .unwrap()
+            .is_active(StatusEffectKind::Regen));
+        assert!(!effects
+            .get(field)
+            .unwrap()
+            .is_active(StatusEffectKind::Regen));
+    }
+
+    #[test]
+    fn spawn_protection_is_lost_when_leaving_the_own_zone() {
+        let mut world = world();
+        let home = ally(&mut world, 10.0);
+        let field = ally(&mut world, 100.0);
+        ProtectionSystem.run_now(&world);
+
+        let effects = world.read_storage::<StatusEffects>();
+        assert!(effects.get(home).unwrap().is_invulnerable());
+        assert!(!effects.get(field).unwrap().is_invulnerable());
+    }
+
+    #[test]
+    fn projectiles_from_the_opposing_zone_deal_less_damage() {
+        let mut world = world();
+        world
+            .create_entity()
+            .with(Ally)
+            .with(Turret {
+                max_strength: 300.0,
+                flight_time: 1.0,
+                ..Turret::default()
+            })
+            .with(Point::new(160.0, 40.0))
+            .with(ProjectileBoundingBox(BoundingBox::new(
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 1.0),
+            )))
+            .with(Damage(10.0))
+            .build();
+        world
+            .create_entity()
+            .with(Enemy)
+            .with(WorldPosition(Point::new(100.0, 40.0)))
+            .with(Walk::new(
+                BoundingBox::new(Point::new(0.0, 0.0), Point::new(2.0, 2.0)),
+                0.0,
+            ))
+            .with(BoundingBox::new(Point::new(0.0, 0.0), Point::new(2.0, 2.0)))
+            .with(UnitState::Walk)
+            .build();
+
+        TurretSystem.run_now(&world);
+        world.maintain();
+
+        let projectiles = world.read_storage::<Projectile>();
+        let dmg = world.read_storage::<Damage>();
+        let damages: Vec<f64> = (&projectiles, &dmg).join().map(|(_, dmg)| dmg.0).collect();
+        assert_eq!(damages, [10.0 * PROTECTED_PROJECTILE_DAMAGE]);
+    }
+}
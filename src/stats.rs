@@ -0,0 +1,86 @@
+use specs::prelude::*;
+
+use super::*;
+
+/// Counters for everything that happened during the battle.
+#[derive(Default, Debug)]
+pub struct Statistics {
+    pub units_recruited: usize,
+    pub allies_lost: usize,
+    pub enemies_killed: usize,
+    /// Enemies killed by archers shooting from the castle, they are also counted as killed.
+    pub garrison_kills: usize,
+    /// Amount of times a unit of either side fled.
+    pub routs: usize,
+    pub projectiles_fired: usize,
+    pub damage_dealt: f64,
+    pub damage_taken: f64,
+    /// Seconds since the battle started.
+    pub duration: f64,
+}
+
+impl Statistics {
+    /// Keep track of damage done to a unit and whether it died because of it.
+    ///
+    /// The health left is after the damage is applied, the damage of a killing blow beyond the
+    /// health the unit had left isn't counted.
+    pub fn register_damage(&mut self, target_is_ally: bool, dmg: f64, health_left: f64) {
+        let died = health_left <= 0.0;
+        let dmg = (dmg + health_left.min(0.0)).max(0.0);
+
+        if target_is_ally {
+            self.damage_taken += dmg;
+            if died {
+                self.allies_lost += 1;
+            }
+        } else {
+            self.damage_dealt += dmg;
+            if died {
+                self.enemies_killed += 1;
+            }
+        }
+    }
+
+    /// The statistics as lines of text with the values aligned in a column.
+    pub fn summary(&self) -> Vec<String> {
+        vec![
+            format!("{:<18}{:>6.0}s", "battle duration", self.duration),
+            format!("{:<18}{:>6}", "units recruited", self.units_recruited),
+            format!("{:<18}{:>6}", "allies lost", self.allies_lost),
+            format!("{:<18}{:>6}", "enemies killed", self.enemies_killed),
+            format!("{:<18}{:>6}", "garrison kills", self.garrison_kills),
+            format!("{:<18}{:>6}", "routs", self.routs),
+            format!("{:<18}{:>6}", "projectiles fired", self.projectiles_fired),
+            format!("{:<18}{:>6.0}", "damage dealt", self.damage_dealt),
+            format!("{:<18}{:>6.0}", "damage taken", self.damage_taken),
+        ]
+    }
+}
+
+pub struct StatisticsSystem;
+impl<'a> System<'a> for StatisticsSystem {
+    type SystemData = (Read<'a, DeltaTime>, Write<'a, Statistics>);
+
+    fn run(&mut self, (dt, mut stats): Self::SystemData) {
+        stats.duration += dt.to_seconds();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn killing_blow_only_counts_health_left() {
+        let mut stats = Statistics::default();
+
+        stats.register_damage(false, 15.0, 5.0);
+        stats.register_damage(false, 15.0, -10.0);
+        stats.register_damage(true, 30.0, -20.0);
+
+        assert_eq!(stats.damage_dealt, 20.0);
+        assert_eq!(stats.enemies_killed, 1);
+        assert_eq!(stats.damage_taken, 10.0);
+        assert_eq!(stats.allies_lost, 1);
+    }
+}
@@ -0,0 +1,362 @@
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+const POISON_COLOR: u32 = 0x6A_BE_30;
+const SLOW_COLOR: u32 = 0x5F_CD_E4;
+const REGEN_COLOR: u32 = 0x99_E5_50;
+const INVULNERABLE_COLOR: u32 = 0xFB_F2_36;
+
+/// The magnitude of poison, the aura and regen is the damage or health per second, of a slow it's
+/// the walking speed multiplier.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StatusEffectKind {
+    Poison,
+    Aura,
+    Slow,
+    Regen,
+    Stagger,
+    StaggerImmunity,
+    Invulnerable,
+}
+
+impl StatusEffectKind {
+    pub fn default_stacking(self) -> Stacking {
+        match self {
+            StatusEffectKind::Poison => Stacking::Stack,
+            _ => Stacking::Refresh,
+        }
+    }
+
+    // The color shown next to the health bar of an affected unit
+    fn color(self) -> Option<u32> {
+        match self {
+            StatusEffectKind::Poison | StatusEffectKind::Aura => Some(POISON_COLOR),
+            StatusEffectKind::Slow => Some(SLOW_COLOR),
+            StatusEffectKind::Regen => Some(REGEN_COLOR),
+            StatusEffectKind::Invulnerable => Some(INVULNERABLE_COLOR),
+            StatusEffectKind::Stagger | StatusEffectKind::StaggerImmunity => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stacking {
+    Refresh,
+    // Adds to the magnitude and keeps the longest duration, slows are multiplied instead
+    Stack,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub magnitude: f64,
+    pub time_left: f64,
+}
+
+impl StatusEffect {
+    pub fn new(kind: StatusEffectKind, magnitude: f64, duration: f64) -> Self {
+        StatusEffect {
+            kind,
+            magnitude,
+            time_left: duration,
+        }
+    }
+
+    pub fn poison(damage_per_second: f64, duration: f64) -> Self {
+        StatusEffect::new(StatusEffectKind::Poison, damage_per_second, duration)
+    }
+
+    pub fn slow(walk_speed_multiplier: f64, duration: f64) -> Self {
+        StatusEffect::new(StatusEffectKind::Slow, walk_speed_multiplier, duration)
+    }
+
+    pub fn regen(heal_per_second: f64, duration: f64) -> Self {
+        StatusEffect::new(StatusEffectKind::Regen, heal_per_second, duration)
+    }
+
+    pub fn timed(kind: StatusEffectKind, duration: f64) -> Self {
+        StatusEffect::new(kind, 1.0, duration)
+    }
+}
+
+/// The status effect a projectile applies to the units it hits, also in its explosion.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct StatusEffectOnHit(pub StatusEffect);
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct StatusTick {
+    pub damage: f64,
+    pub heal: f64,
+}
+
+/// The status effects on a unit, every kind of effect is only active once.
+#[derive(Component, Debug, Default, Clone)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+    // Kinds that don't use their default stacking rule
+    stacking: Vec<(StatusEffectKind, Stacking)>,
+}
+
+impl StatusEffects {
+    pub fn with_effect(mut self, effect: StatusEffect) -> Self {
+        self.apply(effect);
+
+        self
+    }
+
+    pub fn with_stacking(mut self, kind: StatusEffectKind, stacking: Stacking) -> Self {
+        self.stacking.retain(|(other, _)| *other != kind);
+        self.stacking.push((kind, stacking));
+
+        self
+    }
+
+    pub fn stacking(&self, kind: StatusEffectKind) -> Stacking {
+        match self.stacking.iter().find(|(other, _)| *other == kind) {
+            Some((_, stacking)) => *stacking,
+            None => kind.default_stacking(),
+        }
+    }
+
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let stacking = self.stacking(effect.kind);
+        match self
+            .effects
+            .iter_mut()
+            .find(|active| active.kind == effect.kind)
+        {
+            Some(active) => match stacking {
+                Stacking::Refresh => *active = effect,
+                Stacking::Stack => {
+                    if effect.kind == StatusEffectKind::Slow {
+                        active.magnitude *= effect.magnitude;
+                    } else {
+                        active.magnitude += effect.magnitude;
+                    }
+                    active.time_left = active.time_left.max(effect.time_left);
+                }
+            },
+            None => self.effects.push(effect),
+        }
+    }
+
+    pub fn remove(&mut self, kind: StatusEffectKind) {
+        self.effects.retain(|effect| effect.kind != kind);
+    }
+
+    pub fn get(&self, kind: StatusEffectKind) -> Option<&StatusEffect> {
+        self.effects.iter().find(|effect| effect.kind == kind)
+    }
+
+    pub fn is_active(&self, kind: StatusEffectKind) -> bool {
+        self.get(kind).is_some()
+    }
+
+    pub fn is_staggered(&self) -> bool {
+        self.is_active(StatusEffectKind::Stagger)
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.is_active(StatusEffectKind::Invulnerable)
+    }
+
+    pub fn walk_speed(&self, speed: f64) -> f64 {
+        match self.get(StatusEffectKind::Slow) {
+            Some(slow) => speed * slow.magnitude.max(0.0),
+            None => speed,
+        }
+    }
+
+    pub fn tint(&self) -> Option<u32> {
+        self.effects
+            .iter()
+            .rev()
+            .find_map(|effect| effect.kind.color())
+    }
+
+    /// Count down the effects and return the damage and healing, an effect that wears off during
+    /// the step only counts for the part it was active.
+    pub fn update(&mut self, dt: f64) -> StatusTick {
+        let mut tick = StatusTick::default();
+        for effect in self.effects.iter_mut() {
+            let active = effect.time_left.min(dt).max(0.0);
+            match effect.kind {
+                StatusEffectKind::Poison | StatusEffectKind::Aura => {
+                    tick.damage += effect.magnitude * active
+                }
+                StatusEffectKind::Regen => tick.heal += effect.magnitude * active,
+                _ => (),
+            }
+
+            effect.time_left -= dt;
+        }
+        self.effects.retain(|effect| effect.time_left > 0.0);
+
+        tick
+    }
+}
+
+#[derive(SystemData)]
+pub struct StatusEffectSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    updater: Read<'a, LazyUpdate>,
+    ally: ReadStorage<'a, Ally>,
+    pos: ReadStorage<'a, WorldPosition>,
+    health_bar: ReadStorage<'a, HealthBar>,
+    effects: WriteStorage<'a, StatusEffects>,
+    health: WriteStorage<'a, Health>,
+    stats: Write<'a, Statistics>,
+    events: Write<'a, EventLog>,
+}
+
+pub struct StatusEffectSystem;
+impl<'a> System<'a> for StatusEffectSystem {
+    type SystemData = StatusEffectSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+
+        for (e, pos, effects, health) in (
+            &*system_data.entities,
+            &system_data.pos,
+            &mut system_data.effects,
+            &mut system_data.health,
+        )
+            .join()
+        {
+            let invulnerable = effects.is_invulnerable();
+            let tick = effects.update(dt);
+
+            if tick.damage > 0.0 && !invulnerable {
+                let is_ally = system_data.ally.get(e).is_some();
+                let died = reduce_unit_health(
+                    &system_data.entities,
+                    &mut system_data.stats,
+                    e,
+                    is_ally,
+                    health,
+                    tick.damage,
+                );
+                if died {
+                    system_data.events.unit_died(is_ally, pos.0);
+                    spawn_death_effects(&system_data.entities, &system_data.updater, *pos);
+                    continue;
+                }
+            }
+
+            if tick.heal > 0.0 {
+                let max_health = system_data
+                    .health_bar
+                    .get(e)
+                    .map(|health_bar| health_bar.max_health);
+                restore_unit_health(health, max_health, tick.heal);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_expire() {
+        let mut effects = StatusEffects::default().with_effect(StatusEffect::poison(2.0, 1.0));
+
+        assert_eq!(effects.update(0.5).damage, 1.0);
+        assert!(effects.is_active(StatusEffectKind::Poison));
+
+        // Only the part of the step where the poison was still active deals damage
+        assert_eq!(effects.update(1.0).damage, 1.0);
+        assert!(!effects.is_active(StatusEffectKind::Poison));
+        assert_eq!(effects.update(1.0), StatusTick::default());
+    }
+
+    #[test]
+    fn regen_heals() {
+        let mut effects = StatusEffects::default().with_effect(StatusEffect::regen(4.0, 2.0));
+
+        assert_eq!(
+            effects.update(0.5),
+            StatusTick {
+                damage: 0.0,
+                heal: 2.0
+            }
+        );
+    }
+
+    #[test]
+    fn poison_stacks_magnitude_by_default() {
+        let mut effects = StatusEffects::default()
+            .with_effect(StatusEffect::poison(2.0, 1.0))
+            .with_effect(StatusEffect::poison(3.0, 3.0));
+
+        let poison = effects.get(StatusEffectKind::Poison).unwrap();
+        assert_eq!(poison.magnitude, 5.0);
+        assert_eq!(poison.time_left, 3.0);
+
+        // A shorter poison doesn't cut the duration
+        effects.apply(StatusEffect::poison(1.0, 0.5));
+        assert_eq!(
+            effects.get(StatusEffectKind::Poison).unwrap().time_left,
+            3.0
+        );
+    }
+
+    #[test]
+    fn refreshing_restarts_the_duration() {
+        let mut effects = StatusEffects::default().with_effect(StatusEffect::slow(0.5, 2.0));
+        effects.update(1.5);
+
+        effects.apply(StatusEffect::slow(0.8, 2.0));
+        assert_eq!(
+            effects.get(StatusEffectKind::Slow),
+            Some(&StatusEffect::slow(0.8, 2.0))
+        );
+    }
+
+    #[test]
+    fn stacking_is_configurable() {
+        let mut effects = StatusEffects::default()
+            .with_stacking(StatusEffectKind::Poison, Stacking::Refresh)
+            .with_stacking(StatusEffectKind::Slow, Stacking::Stack)
+            .with_effect(StatusEffect::poison(2.0, 1.0))
+            .with_effect(StatusEffect::slow(0.5, 1.0));
+
+        effects.apply(StatusEffect::poison(3.0, 1.0));
+        effects.apply(StatusEffect::slow(0.5, 1.0));
+
+        assert_eq!(
+            effects.get(StatusEffectKind::Poison).unwrap().magnitude,
+            3.0
+        );
+        assert_eq!(effects.walk_speed(20.0), 5.0);
+    }
+
+    #[test]
+    fn slow_keeps_the_walking_direction() {
+        let effects = StatusEffects::default().with_effect(StatusEffect::slow(0.5, 1.0));
+
+        // Enemies walk to the left with a negative speed
+        assert_eq!(effects.walk_speed(20.0), 10.0);
+        assert_eq!(effects.walk_speed(-20.0), -10.0);
+
+        // A slow can stop a unit but never turn it around
+        let effects = StatusEffects::default().with_effect(StatusEffect::slow(-1.0, 1.0));
+        assert_eq!(effects.walk_speed(-20.0), 0.0);
+        assert!(effects.walk_speed(20.0) >= 0.0);
+    }
+
+    #[test]
+    fn no_effects_changes_nothing() {
+        let effects = StatusEffects::default();
+
+        assert_eq!(effects.walk_speed(-15.0), -15.0);
+        assert!(!effects.is_staggered());
+        assert!(!effects.is_invulnerable());
+        assert_eq!(effects.tint(), None);
+    }
+}
@@ -1,28 +1,308 @@
 use line_drawing::Bresenham;
 use specs::*;
 use specs_derive::Component;
+use std::collections::HashMap;
 
 use crate::geom::*;
 use crate::physics::*;
 
+// Amount of pixels around a position used to estimate the surface normal
+const NORMAL_RADIUS: i32 = 2;
+
+// Width in pixels of the darkened ring around a crater
+const SCORCH_WIDTH: f64 = 3.0;
+// How much of the brightness is left at the darkest part of the scorch marks
+const SCORCH_INTENSITY: f64 = 0.6;
+// Seconds the rim of a new crater keeps glowing
+const RIM_GLOW_TIME: f64 = 2.0;
+const RIM_GLOW_COLOR: u32 = 0xFF_F0_A0_40;
+// The color of a pixel without terrain
+const EMPTY_COLOR: u32 = 0xFF_FF_00_FF;
+
+/// What a terrain pixel is made of, harder materials are harder to blow away.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Material {
+    Dirt,
+    Rock,
+}
+
+impl Material {
+    pub fn hardness(self) -> f64 {
+        match self {
+            Material::Dirt => 1.0,
+            Material::Rock => 3.0,
+        }
+    }
+
+    pub fn recolor(self, color: u32) -> u32 {
+        match self {
+            Material::Dirt => color,
+            Material::Rock => {
+                // Grey with the same brightness as the original color
+                let grey = (((color >> 16) & 0xFF) + ((color >> 8) & 0xFF) + (color & 0xFF)) / 3;
+                0xFF_00_00_00 | grey << 16 | grey << 8 | grey
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct FadingPixel {
+    // The color of the terrain below the temporary color
+    color: u32,
+    temporary: u32,
+    time_left: f64,
+    lifetime: f64,
+}
+
 #[derive(Default)]
 pub struct Terrain {
     pub buffer: Vec<u32>,
+    // The material of every pixel in the buffer
+    materials: Vec<Material>,
 
     width: usize,
     height: usize,
+    // Area changed since the last time the terrain was drawn, as (x1, y1, x2, y2)
+    dirty: Option<(usize, usize, usize, usize)>,
+    // Crater rims and decals that are still fading by their index in the buffer
+    fading: HashMap<usize, FadingPixel>,
 }
 
 impl Terrain {
     pub fn new(size: (usize, usize)) -> Self {
         Terrain {
-            buffer: vec![0xFF_FF_00_FF; (size.0 * size.1) as usize],
+            buffer: vec![EMPTY_COLOR; size.0 * size.1],
+            materials: vec![Material::Dirt; size.0 * size.1],
 
             width: size.0,
             height: size.1,
+            dirty: Some((0, 0, size.0, size.1)),
+            fading: HashMap::new(),
+        }
+    }
+
+    /// Turn all solid pixels at and below the height into rock.
+    pub fn generate_materials(&mut self, rock_level: usize) {
+        let start = rock_level.min(self.height) * self.width;
+        for index in start..self.buffer.len() {
+            if (self.buffer[index] & 0xFF_FF_FF) == 0xFF_00_FF {
+                continue;
+            }
+
+            self.materials[index] = Material::Rock;
+            self.buffer[index] = Material::Rock.recolor(self.buffer[index]);
+        }
+
+        self.mark_dirty(
+            (0, rock_level as i32),
+            (self.width as i32, self.height as i32),
+        );
+    }
+
+    /// Remove the terrain in the rectangle around the center with the function, pixels of hard
+    /// materials survive when they are further away than the radius divided by their hardness.
+    pub fn remove_with<F>(&mut self, center: (i32, i32), size: (i32, i32), remove: F)
+    where
+        F: FnOnce(&mut Vec<u32>),
+    {
+        let pos = (center.0 - size.0 / 2, center.1 - size.1 / 2);
+        let radius = f64::from(size.0.max(size.1)) / 2.0;
+
+        // Remember the hard pixels that are out of reach
+        let mut resisting = Vec::new();
+        for y in pos.1.max(0)..(pos.1 + size.1).min(self.height as i32) {
+            for x in pos.0.max(0)..(pos.0 + size.0).min(self.width as i32) {
+                let index = x as usize + y as usize * self.width;
+                let hardness = self.materials[index].hardness();
+                if hardness <= 1.0 || (self.buffer[index] & 0xFF_FF_FF) == 0xFF_00_FF {
+                    continue;
+                }
+
+                let dx = f64::from(x - center.0);
+                let dy = f64::from(y - center.1);
+                if (dx * dx + dy * dy).sqrt() > radius / hardness {
+                    resisting.push((index, self.buffer[index]));
+                }
+            }
+        }
+
+        remove(&mut self.buffer);
+
+        for (index, color) in resisting {
+            self.buffer[index] = color;
+        }
+
+        // The removed pixels don't keep their material, terrain added there later is dirt
+        for y in pos.1.max(0)..(pos.1 + size.1).min(self.height as i32) {
+            for x in pos.0.max(0)..(pos.0 + size.0).min(self.width as i32) {
+                let index = x as usize + y as usize * self.width;
+                if !self.is_solid(index) {
+                    self.materials[index] = Material::Dirt;
+                }
+            }
+        }
+        self.mark_dirty(pos, size);
+    }
+
+    /// Darken the terrain in a ring around a crater and let the new rim glow.
+    pub fn scorch(&mut self, center: (i32, i32), radius: f64) {
+        let outer = radius + SCORCH_WIDTH;
+        let reach = outer.ceil() as i32;
+        let x_range = (center.0 - reach).max(0)..(center.0 + reach + 1).min(self.width as i32);
+        let y_range = (center.1 - reach).max(0)..(center.1 + reach + 1).min(self.height as i32);
+        let is_in_area = |index: usize| {
+            let (x, y) = ((index % self.width) as i32, (index / self.width) as i32);
+            x_range.contains(&x) && y_range.contains(&y)
+        };
+
+        // Reset the fading pixels of older craters here, they are removed, part of the new rim or
+        // scorched again
+        let (overlapping, fading): (HashMap<usize, FadingPixel>, HashMap<usize, FadingPixel>) =
+            self.fading
+                .iter()
+                .partition(|(index, _)| is_in_area(**index));
+        self.fading = fading;
+        for (index, pixel) in overlapping {
+            if self.is_solid(index) {
+                self.buffer[index] = pixel.color;
+            }
+        }
+
+        for y in y_range.clone() {
+            for x in x_range.clone() {
+                let index = x as usize + y as usize * self.width;
+                if !self.is_solid(index) {
+                    continue;
+                }
+
+                let dx = f64::from(x - center.0);
+                let dy = f64::from(y - center.1);
+                let dist = (dx * dx + dy * dy).sqrt();
+                if dist > outer {
+                    continue;
+                }
+
+                // The scorch marks get lighter further away from the crater
+                let fraction = ((dist - radius) / SCORCH_WIDTH).max(0.0);
+                let scale = SCORCH_INTENSITY + (1.0 - SCORCH_INTENSITY) * fraction;
+                let color = blend(0xFF_00_00_00, self.buffer[index], scale);
+                self.buffer[index] = color;
+
+                // Pixels next to the hole are part of the rim
+                let touches_hole = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(nx, ny)| {
+                    let (nx, ny) = (x + nx, y + ny);
+                    nx >= 0
+                        && ny >= 0
+                        && (nx as usize) < self.width
+                        && (ny as usize) < self.height
+                        && !self.is_solid(nx as usize + ny as usize * self.width)
+                });
+                if touches_hole && dist <= radius + 1.0 {
+                    self.fading.insert(
+                        index,
+                        FadingPixel {
+                            color,
+                            temporary: RIM_GLOW_COLOR,
+                            time_left: RIM_GLOW_TIME,
+                            lifetime: RIM_GLOW_TIME,
+                        },
+                    );
+                    self.buffer[index] = RIM_GLOW_COLOR;
+                }
+            }
+        }
+
+        self.mark_dirty(
+            (x_range.start, y_range.start),
+            (x_range.end - x_range.start, y_range.end - y_range.start),
+        );
+    }
+
+    /// Color the solid terrain pixels at the offsets from the position for the lifetime.
+    pub fn stamp_fading_decal(
+        &mut self,
+        pos: (i32, i32),
+        pixels: &[((i32, i32), u32)],
+        lifetime: f64,
+    ) {
+        for ((dx, dy), temporary) in pixels {
+            let (x, y) = (pos.0 + dx, pos.1 + dy);
+            if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                continue;
+            }
+
+            let index = x as usize + y as usize * self.width;
+            if !self.is_solid(index) {
+                continue;
+            }
+
+            // Replace a decal that is still fading so it doesn't fade back to its color
+            let color = match self.fading.remove(&index) {
+                Some(existing) => existing.color,
+                None => self.buffer[index],
+            };
+
+            self.fading.insert(
+                index,
+                FadingPixel {
+                    color,
+                    temporary: *temporary,
+                    time_left: lifetime,
+                    lifetime,
+                },
+            );
+            self.buffer[index] = *temporary;
+            self.mark_dirty((x, y), (1, 1));
         }
     }
 
+    /// Fade the crater rims and decals back to their own color.
+    pub fn update_fading(&mut self, dt: f64) {
+        let mut fading = std::mem::take(&mut self.fading);
+        fading.retain(|index, _| self.is_solid(*index));
+
+        for (index, pixel) in fading.iter_mut() {
+            pixel.time_left -= dt;
+            let fraction = (pixel.time_left / pixel.lifetime).max(0.0);
+            self.buffer[*index] = blend(pixel.color, pixel.temporary, fraction);
+
+            let (x, y) = (index % self.width, index / self.width);
+            self.mark_dirty((x as i32, y as i32), (1, 1));
+        }
+        fading.retain(|_, pixel| pixel.time_left > 0.0);
+
+        self.fading = fading;
+    }
+
+    fn is_solid(&self, index: usize) -> bool {
+        (self.buffer[index] & 0xFF_FF_FF) != 0xFF_00_FF
+    }
+
+    pub fn mark_dirty(&mut self, pos: (i32, i32), size: (i32, i32)) {
+        let x1 = pos.0.max(0) as usize;
+        let y1 = pos.1.max(0) as usize;
+        let x2 = ((pos.0 + size.0).max(0) as usize).min(self.width);
+        let y2 = ((pos.1 + size.1).max(0) as usize).min(self.height);
+        if x1 >= x2 || y1 >= y2 {
+            return;
+        }
+
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => (
+                dirty.0.min(x1),
+                dirty.1.min(y1),
+                dirty.2.max(x2),
+                dirty.3.max(y2),
+            ),
+            None => (x1, y1, x2, y2),
+        });
+    }
+
+    pub fn take_dirty(&mut self) -> Option<(usize, usize, usize, usize)> {
+        self.dirty.take()
+    }
+
     pub fn size(&self) -> (usize, usize) {
         (self.width, self.height)
     }
@@ -44,6 +324,23 @@ impl Terrain {
         None
     }
 
+    /// Whether there is no terrain between the positions, the terrain the line starts in is
+    /// skipped so a turret inside a castle can look out of it.
+    pub fn line_of_sight(&self, start: (i32, i32), end: (i32, i32)) -> bool {
+        let (width, height) = self.size();
+        let is_solid = |pos: (i32, i32)| {
+            pos.0 >= 0
+                && pos.1 >= 0
+                && (pos.0 as usize) < width
+                && (pos.1 as usize) < height
+                && self.is_solid(pos.0 as usize + pos.1 as usize * width)
+        };
+
+        Bresenham::new(start, end)
+            .skip_while(|pos| is_solid(*pos))
+            .all(|pos| !is_solid(pos))
+    }
+
     pub fn rect_collides(&self, rect: BoundingBox) -> Option<(i32, i32)> {
         let mut rect = rect.to_i32();
 
@@ -80,15 +377,113 @@ impl Terrain {
         None
     }
 
+    /// Estimate the direction pointing away from the terrain surface at the position.
+    pub fn normal_at(&self, pos: (i32, i32)) -> Option<(f64, f64)> {
+        let (width, height) = self.size();
+
+        // Sum the directions towards all empty pixels around the position
+        let (mut nx, mut ny) = (0.0, 0.0);
+        for dy in -NORMAL_RADIUS..=NORMAL_RADIUS {
+            for dx in -NORMAL_RADIUS..=NORMAL_RADIUS {
+                let (x, y) = (pos.0 + dx, pos.1 + dy);
+                if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                    continue;
+                }
+
+                if (self.buffer[x as usize + y as usize * width] & 0xFF_FF_FF) == 0xFF_00_FF {
+                    nx += f64::from(dx);
+                    ny += f64::from(dy);
+                }
+            }
+        }
+
+        let length = (nx * nx + ny * ny).sqrt();
+        if length < f64::EPSILON {
+            return None;
+        }
+
+        Some((nx / length, ny / length))
+    }
+
+    /// Every vertical run of solid pixels in the column as the top and bottom y, from top to
+    /// bottom.
+    pub fn surfaces_at(&self, x: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut y = if x < self.width { 0 } else { self.height };
+
+        std::iter::from_fn(move || {
+            while y < self.height && !self.is_solid(x + y * self.width) {
+                y += 1;
+            }
+            if y >= self.height {
+                return None;
+            }
+
+            let top = y;
+            while y < self.height && self.is_solid(x + y * self.width) {
+                y += 1;
+            }
+
+            Some((top, y - 1))
+        })
+    }
+
+    /// Find the first solid pixel in the column at or below the point, or the surface above it
+    /// when the point is buried in the terrain.
+    pub fn surface_below(&self, point: Point) -> Option<Point> {
+        let (width, height) = self.size();
+        if point.x < 0.0 || point.x as usize >= width {
+            return None;
+        }
+
+        let x = point.x as usize;
+        let start_y = (point.y.max(0.0) as usize).min(height - 1);
+        let is_solid = |y: usize| (self.buffer[x + y * width] & 0xFF_FF_FF) != 0xFF_00_FF;
+
+        let surface_y = if is_solid(start_y) {
+            // Walk up until the pixel above is empty
+            (0..=start_y).rev().take_while(|y| is_solid(*y)).last()
+        } else {
+            (start_y..height).find(|y| is_solid(*y))
+        };
+
+        surface_y.map(|y| Point::new(x as f64, y as f64))
+    }
+
+    pub fn stamp_decal(&mut self, pos: (i32, i32), pixels: &[((i32, i32), u32)]) {
+        for ((dx, dy), color) in pixels {
+            let (x, y) = (pos.0 + dx, pos.1 + dy);
+            if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                continue;
+            }
+
+            let index = x as usize + y as usize * self.width;
+            if (self.buffer[index] & 0xFF_FF_FF) != 0xFF_00_FF {
+                self.buffer[index] = *color;
+                self.mark_dirty((x, y), (1, 1));
+            }
+        }
+    }
+
     pub fn draw_pixel(&mut self, pos: (usize, usize), color: u32) {
         if pos.0 >= self.width || pos.1 >= self.height {
             return;
         }
 
         self.buffer[pos.0 + pos.1 * self.width] = color;
+        self.mark_dirty((pos.0 as i32, pos.1 as i32), (1, 1));
     }
 }
 
+fn blend(from: u32, to: u32, fraction: f64) -> u32 {
+    let channel = |shift: u32| {
+        let from = f64::from((from >> shift) & 0xFF);
+        let to = f64::from((to >> shift) & 0xFF);
+        ((from + (to - from) * fraction) as u32 & 0xFF) << shift
+    };
+
+    0xFF_00_00_00 | channel(16) | channel(8) | channel(0)
+}
+
 #[derive(Component, Debug)]
 pub struct TerrainMask {
     pub id: usize,
@@ -102,6 +497,8 @@ impl TerrainMask {
     }
 }
 
+// The area is only read once collapsing is implemented
+#[allow(dead_code)]
 #[derive(Component, Debug)]
 pub struct TerrainCollapse(pub BoundingBox);
 
@@ -122,3 +519,192 @@ impl<'a> System<'a> for TerrainCollapseSystem {
         }
     }
 }
+
+pub struct TerrainFadeSystem;
+impl<'a> System<'a> for TerrainFadeSystem {
+    type SystemData = (Read<'a, DeltaTime>, Write<'a, Terrain>);
+
+    fn run(&mut self, (dt, mut terrain): Self::SystemData) {
+        terrain.update_fading(dt.to_seconds());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A terrain with the rows from the top filled with solid dirt
+    fn terrain(size: (usize, usize), solid_rows: &[usize]) -> Terrain {
+        let mut terrain = Terrain::new(size);
+        for y in solid_rows {
+            for x in 0..size.0 {
+                terrain.buffer[x + y * size.0] = 0xFF_80_60_40;
+            }
+        }
+
+        terrain
+    }
+
+    #[test]
+    fn surfaces_are_found_from_top_to_bottom() {
+        let mut terrain = terrain((4, 10), &[9]);
+        // A tunnel in the second column with a roof of three pixels
+        for y in 2..5 {
+            terrain.buffer[1 + y * 4] = 0xFF_80_60_40;
+        }
+
+        let spans: Vec<(usize, usize)> = terrain.surfaces_at(1).collect();
+        assert_eq!(spans, vec![(2, 4), (9, 9)]);
+        let spans: Vec<(usize, usize)> = terrain.surfaces_at(0).collect();
+        assert_eq!(spans, vec![(9, 9)]);
+
+        // Columns outside the terrain have no surfaces
+        assert_eq!(terrain.surfaces_at(4).count(), 0);
+        assert_eq!(Terrain::new((4, 10)).surfaces_at(0).count(), 0);
+    }
+
+    #[test]
+    fn removed_pixels_lose_their_material() {
+        let mut terrain = terrain((4, 4), &[2, 3]);
+        terrain.generate_materials(0);
+        let index = 1 + 2 * 4;
+        assert_eq!(terrain.materials[index], Material::Rock);
+
+        terrain.remove_with((1, 2), (1, 1), |buffer| buffer[index] = EMPTY_COLOR);
+        assert_eq!(terrain.materials[index], Material::Dirt);
+        // The rock around it is untouched
+        assert_eq!(terrain.materials[index + 1], Material::Rock);
+    }
+
+    #[test]
+    fn restamping_a_fading_decal_fades_back_to_the_terrain() {
+        let mut terrain = terrain((2, 2), &[1]);
+        let decal = [((0, 0), 0xFF_00_00_00)];
+
+        terrain.stamp_fading_decal((0, 1), &decal, 1.0);
+        terrain.update_fading(0.5);
+        terrain.stamp_fading_decal((0, 1), &decal, 1.0);
+        assert_eq!(terrain.fading.len(), 1);
+
+        terrain.update_fading(1.0);
+        assert!(terrain.fading.is_empty());
+        assert_eq!(terrain.buffer[2], 0xFF_80_60_40);
+    }
+
+    #[test]
+    fn crater_removes_dirt_fully_and_rock_partially() {
+        let rows: Vec<usize> = (0..21).collect();
+        let mut terrain = terrain((21, 21), &rows);
+        terrain.generate_materials(10);
+
+        // Remove a circle with a radius of 10 pixels around the boundary
+        terrain.remove_with((10, 10), (20, 20), |buffer| {
+            for y in 0..21 {
+                for x in 0..21 {
+                    let (dx, dy) = (x as f64 - 10.0, y as f64 - 10.0);
+                    if (dx * dx + dy * dy).sqrt() <= 10.0 {
+                        buffer[x + y * 21] = EMPTY_COLOR;
+                    }
+                }
+            }
+        });
+
+        // The dirt is removed in the whole radius, the rock only close to the center
+        assert!(!terrain.is_solid(10 + 2 * 21));
+        assert!(!terrain.is_solid(10 + 9 * 21));
+        assert!(!terrain.is_solid(10 + 12 * 21));
+        assert!(terrain.is_solid(10 + 15 * 21));
+        assert!(terrain.is_solid(10 + 18 * 21));
+        assert_eq!(terrain.materials[10 + 18 * 21], Material::Rock);
+    }
+
+    // A fully solid terrain with a round hole
+    fn crater(radius: f64) -> Terrain {
+        let rows: Vec<usize> = (0..21).collect();
+        let mut terrain = terrain((21, 21), &rows);
+        dig(&mut terrain, radius);
+
+        terrain
+    }
+
+    fn dig(terrain: &mut Terrain, radius: f64) {
+        for y in 0..21 {
+            for x in 0..21 {
+                let (dx, dy) = (x as f64 - 10.0, y as f64 - 10.0);
+                if (dx * dx + dy * dy).sqrt() <= radius {
+                    terrain.buffer[x + y * 21] = EMPTY_COLOR;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scorch_glows_at_the_rim_and_darkens_the_ring() {
+        let mut terrain = crater(3.0);
+        terrain.scorch((10, 10), 3.0);
+
+        // Right next to the hole
+        assert_eq!(terrain.buffer[14 + 10 * 21], RIM_GLOW_COLOR);
+        assert!(terrain.fading.contains_key(&(14 + 10 * 21)));
+        // Further away it's only darkened, fading out towards the edge of the ring
+        assert_ne!(terrain.buffer[15 + 10 * 21], 0xFF_80_60_40);
+        assert!(!terrain.fading.contains_key(&(15 + 10 * 21)));
+        assert_eq!(
+            terrain.buffer[16 + 10 * 21],
+            blend(0xFF_00_00_00, 0xFF_80_60_40, 1.0)
+        );
+        assert_eq!(terrain.buffer[18 + 10 * 21], 0xFF_80_60_40);
+
+        // Every glowing pixel touches the hole
+        for index in terrain.fading.keys() {
+            let (x, y) = ((index % 21) as f64 - 10.0, (index / 21) as f64 - 10.0);
+            assert!((x * x + y * y).sqrt() <= 4.0);
+        }
+    }
+
+    #[test]
+    fn rim_fades_back_to_the_scorched_color() {
+        let mut terrain = crater(3.0);
+        terrain.scorch((10, 10), 3.0);
+
+        let scale = SCORCH_INTENSITY + (1.0 - SCORCH_INTENSITY) / SCORCH_WIDTH;
+        let scorched = blend(0xFF_00_00_00, 0xFF_80_60_40, scale);
+        terrain.update_fading(RIM_GLOW_TIME / 2.0);
+        assert_ne!(terrain.buffer[14 + 10 * 21], scorched);
+        terrain.update_fading(RIM_GLOW_TIME / 2.0);
+        assert_eq!(terrain.buffer[14 + 10 * 21], scorched);
+        assert!(terrain.fading.is_empty());
+    }
+
+    #[test]
+    fn bigger_crater_moves_the_rim_outwards() {
+        let mut terrain = crater(3.0);
+        terrain.scorch((10, 10), 3.0);
+
+        dig(&mut terrain, 5.0);
+        terrain.scorch((10, 10), 5.0);
+
+        // The old rim is removed and the new one glows
+        assert!(!terrain.fading.contains_key(&(14 + 10 * 21)));
+        assert!(terrain.fading.contains_key(&(16 + 10 * 21)));
+        assert!(terrain.fading.keys().all(|index| terrain.is_solid(*index)));
+    }
+
+    #[test]
+    fn dirty_rectangles_are_merged_and_clipped() {
+        let mut terrain = Terrain::new((20, 10));
+        // Everything is dirty at the start
+        assert_eq!(terrain.take_dirty(), Some((0, 0, 20, 10)));
+        assert_eq!(terrain.take_dirty(), None);
+
+        terrain.mark_dirty((2, 3), (2, 2));
+        terrain.mark_dirty((10, 1), (3, 1));
+        assert_eq!(terrain.take_dirty(), Some((2, 1, 13, 5)));
+
+        // Parts outside of the terrain are cut off and areas completely outside are ignored
+        terrain.mark_dirty((-5, 8), (10, 10));
+        terrain.mark_dirty((30, 0), (5, 5));
+        terrain.mark_dirty((5, -4), (2, 2));
+        assert_eq!(terrain.take_dirty(), Some((0, 8, 5, 10)));
+    }
+}
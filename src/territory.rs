@@ -0,0 +1,194 @@
+use specs::prelude::*;
+
+use super::*;
+
+// Seconds between calculating the front line
+const FRONT_LINE_INTERVAL: f64 = 0.5;
+// A unit only holds its position when this many units of its side, including itself, are within
+// the window around it
+const HOLD_UNITS: usize = 3;
+const HOLD_WINDOW: f64 = 40.0;
+
+pub const FRONT_LINE_COLOR: u32 = 0xFF_C8_B4_96;
+// Length in pixels of the dashes of the front line marker and the gaps between them
+pub const FRONT_LINE_DASH: usize = 2;
+
+/// The furthest positions held by both sides, the area between them is contested.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FrontLine {
+    /// The furthest position held by the allies, they advance to the right.
+    pub ally: Option<f64>,
+    /// The furthest position held by the enemies, they advance to the left.
+    pub enemy: Option<f64>,
+    /// The fraction of the level controlled by the allies.
+    pub ally_control: f64,
+    /// The fraction of the level controlled by the enemies.
+    pub enemy_control: f64,
+
+    update_left: f64,
+}
+
+impl FrontLine {
+    /// The position of the front line, `None` when neither side holds any position.
+    pub fn x(&self) -> Option<f64> {
+        match (self.ally, self.enemy) {
+            (Some(ally), Some(enemy)) => Some((ally + enemy) / 2.0),
+            (Some(x), None) | (None, Some(x)) => Some(x),
+            (None, None) => None,
+        }
+    }
+
+    /// The front line as lines of text with the values aligned in a column.
+    pub fn summary(&self) -> Vec<String> {
+        vec![
+            match self.x() {
+                Some(x) => format!("{:<18}{:>6.0}", "front line", x),
+                None => format!("{:<18}{:>6}", "front line", "-"),
+            },
+            format!("{:<18}{:>5.0}%", "ally control", self.ally_control * 100.0),
+            format!(
+                "{:<18}{:>5.0}%",
+                "enemy control",
+                self.enemy_control * 100.0
+            ),
+        ]
+    }
+
+    fn update(&mut self, allies: &[f64], enemies: &[f64], level_width: f64) {
+        self.ally = held_positions(allies).fold(None, |max, x| match max {
+            Some(max) if max >= x => Some(max),
+            _ => Some(x),
+        });
+        self.enemy = held_positions(enemies).fold(None, |min, x| match min {
+            Some(min) if min <= x => Some(min),
+            _ => Some(x),
+        });
+
+        // Without a held position a side only controls the area behind its castle
+        let mut ally_front = self.ally.unwrap_or(0.0);
+        let mut enemy_front = self.enemy.unwrap_or(level_width);
+        if ally_front > enemy_front {
+            // The units are mixed, split the overlapping area at the middle
+            ally_front = (ally_front + enemy_front) / 2.0;
+            enemy_front = ally_front;
+        }
+
+        self.ally_control = (ally_front / level_width).clamp(0.0, 1.0);
+        self.enemy_control = ((level_width - enemy_front) / level_width).clamp(0.0, 1.0);
+    }
+}
+
+// The positions of the units that have enough units of their side close by to hold it
+fn held_positions(positions: &[f64]) -> impl Iterator<Item = f64> + '_ {
+    positions.iter().copied().filter(move |x| {
+        positions
+            .iter()
+            .filter(|other| (*other - x).abs() <= HOLD_WINDOW / 2.0)
+            .count()
+            >= HOLD_UNITS
+    })
+}
+
+#[derive(SystemData)]
+pub struct FrontLineSystemData<'a> {
+    dt: Read<'a, DeltaTime>,
+    terrain: Read<'a, Terrain>,
+    ally: ReadStorage<'a, Ally>,
+    enemy: ReadStorage<'a, Enemy>,
+    pos: ReadStorage<'a, WorldPosition>,
+    state: ReadStorage<'a, UnitState>,
+    front_line: Write<'a, FrontLine>,
+}
+
+pub struct FrontLineSystem;
+impl<'a> System<'a> for FrontLineSystem {
+    type SystemData = FrontLineSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let front_line = &mut *system_data.front_line;
+        front_line.update_left -= system_data.dt.to_seconds();
+        if front_line.update_left > 0.0 {
+            return;
+        }
+        front_line.update_left = FRONT_LINE_INTERVAL;
+
+        // Fleeing units don't hold anything
+        let allies: Vec<f64> = (&system_data.ally, &system_data.pos, &system_data.state)
+            .join()
+            .filter(|(_, _, state)| **state != UnitState::Flee)
+            .map(|(_, pos, _)| pos.0.x)
+            .collect();
+        let enemies: Vec<f64> = (&system_data.enemy, &system_data.pos, &system_data.state)
+            .join()
+            .filter(|(_, _, state)| **state != UnitState::Flee)
+            .map(|(_, pos, _)| pos.0.x)
+            .collect();
+
+        front_line.update(&allies, &enemies, system_data.terrain.size().0 as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(positions: &[f64]) -> Vec<f64> {
+        held_positions(positions).collect()
+    }
+
+    #[test]
+    fn positions_need_enough_units_nearby() {
+        assert!(held(&[]).is_empty());
+        // Two units are not enough to hold a position
+        assert!(held(&[100.0, 105.0]).is_empty());
+        assert_eq!(held(&[100.0, 105.0, 110.0]), vec![100.0, 105.0, 110.0]);
+
+        // The outer units only have one other unit within the window around them
+        assert_eq!(held(&[80.0, 100.0, 120.0]), vec![100.0]);
+        assert!(held(&[79.0, 100.0, 120.0]).is_empty());
+    }
+
+    #[test]
+    fn sides_without_units_hold_nothing() {
+        let mut front_line = FrontLine::default();
+        front_line.update(&[], &[], 400.0);
+
+        assert_eq!(front_line.ally, None);
+        assert_eq!(front_line.enemy, None);
+        assert_eq!(front_line.x(), None);
+        assert_eq!(front_line.ally_control, 0.0);
+        assert_eq!(front_line.enemy_control, 0.0);
+
+        // A single side holds up to its furthest position
+        front_line.update(&[90.0, 95.0, 100.0, 300.0], &[], 400.0);
+        assert_eq!(front_line.ally, Some(100.0));
+        assert_eq!(front_line.x(), Some(100.0));
+        assert_eq!(front_line.ally_control, 0.25);
+        assert_eq!(front_line.enemy_control, 0.0);
+    }
+
+    #[test]
+    fn front_line_is_between_both_sides() {
+        let mut front_line = FrontLine::default();
+        front_line.update(&[90.0, 95.0, 100.0], &[300.0, 310.0, 320.0, 150.0], 400.0);
+
+        // The lone enemy that advanced doesn't hold its position
+        assert_eq!(front_line.ally, Some(100.0));
+        assert_eq!(front_line.enemy, Some(300.0));
+        assert_eq!(front_line.x(), Some(200.0));
+        assert_eq!(front_line.ally_control, 0.25);
+        assert_eq!(front_line.enemy_control, 0.25);
+    }
+
+    #[test]
+    fn interleaved_units_split_the_contested_area() {
+        let mut front_line = FrontLine::default();
+        front_line.update(&[180.0, 200.0, 220.0], &[170.0, 190.0, 210.0], 400.0);
+
+        // Units of the other side don't help holding a position
+        assert_eq!(front_line.ally, Some(200.0));
+        assert_eq!(front_line.enemy, Some(190.0));
+        assert_eq!(front_line.ally_control, 0.4875);
+        assert_eq!(front_line.enemy_control, 0.5125);
+    }
+}
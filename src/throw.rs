@@ -0,0 +1,248 @@
+use cgmath::Point2;
+use specs::prelude::*;
+
+use super::*;
+
+const SPEAR_COLOR: u32 = 0x66_39_31;
+const SPEAR_TRAIL_COLOR: u32 = 0xFF_CB_DB_FC;
+pub const PREDICTION_COLOR: u32 = 0xFF_FF_FF_FF;
+
+// The spear is thrown from the window of the allied castle, mirroring the enemy arrow turret
+const THROW_ORIGIN: (f64, f64) = (25.0, 315.0);
+
+// Seconds the mouse button needs to be held for a throw at full strength
+const FULL_CHARGE_TIME: f64 = 1.5;
+const MIN_THROW_SPEED: f64 = 50.0;
+const MAX_THROW_SPEED: f64 = 250.0;
+const THROW_COOLDOWN: f64 = 2.0;
+
+// How far ahead the predicted arc is calculated and with what interval in seconds
+const PREDICTION_TIME: f64 = 3.0;
+const PREDICTION_STEP: f64 = 0.05;
+
+/// The spear the player can throw by holding and releasing the right mouse button.
+#[derive(Default)]
+pub struct SpearThrow {
+    /// Seconds the mouse button has been held down.
+    pub charge_time: f64,
+    /// Seconds until a new spear can be thrown.
+    pub cooldown_left: f64,
+    /// Predicted flight path of the spear while charging.
+    pub arc: Vec<Point2<usize>>,
+
+    target: (i32, i32),
+    mouse_down: bool,
+    charging: bool,
+}
+
+impl SpearThrow {
+    pub fn handle_mouse(&mut self, pos: (i32, i32), right_is_down: bool) {
+        self.target = pos;
+        self.mouse_down = right_is_down;
+    }
+
+    /// Stop charging without throwing the spear.
+    pub fn cancel(&mut self) {
+        self.mouse_down = false;
+        self.charging = false;
+        self.charge_time = 0.0;
+    }
+
+    /// The strength of the throw between 0.0 and 1.0.
+    pub fn charge_fraction(&self) -> f64 {
+        (self.charge_time / FULL_CHARGE_TIME).min(1.0)
+    }
+
+    /// The velocity the spear would be thrown with from the origin towards the mouse cursor.
+    pub fn launch_velocity(&self, origin: Point) -> Velocity {
+        let dx = self.target.0 as f64 - origin.x;
+        let dy = self.target.1 as f64 - origin.y;
+        // Prevent a division by zero when the cursor is exactly on the origin
+        let length = (dx * dx + dy * dy).sqrt().max(1.0);
+
+        let speed = MIN_THROW_SPEED + (MAX_THROW_SPEED - MIN_THROW_SPEED) * self.charge_fraction();
+
+        Velocity::new(dx / length * speed, dy / length * speed)
+    }
+}
+
+/// Where the spear is thrown from, it's moved above the terrain when the castle covers the window
+/// so the spear doesn't hit the castle right away.
+fn throw_origin(terrain: &Terrain) -> Point {
+    let origin = Point::new(THROW_ORIGIN.0, THROW_ORIGIN.1);
+    match terrain.surface_below(origin) {
+        Some(surface) if surface.y <= origin.y => Point::new(origin.x, surface.y - 1.0),
+        _ => origin,
+    }
+}
+
+#[derive(SystemData)]
+pub struct SpearThrowSystemData<'a> {
+    entities: Entities<'a>,
+    dt: Read<'a, DeltaTime>,
+    grav: Read<'a, Gravity>,
+    terrain: Read<'a, Terrain>,
+    throw: Write<'a, SpearThrow>,
+    stats: Write<'a, Statistics>,
+    updater: Read<'a, LazyUpdate>,
+}
+
+pub struct SpearThrowSystem;
+impl<'a> System<'a> for SpearThrowSystem {
+    type SystemData = SpearThrowSystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        let dt = system_data.dt.to_seconds();
+        let grav = system_data.grav.0;
+        let origin = throw_origin(&system_data.terrain);
+
+        let throw = &mut *system_data.throw;
+        throw.cooldown_left = (throw.cooldown_left - dt).max(0.0);
+        throw.arc.clear();
+
+        if throw.mouse_down {
+            // Charging can only start when the last throw is cooled down
+            if throw.cooldown_left > 0.0 {
+                return;
+            }
+
+            throw.charging = true;
+            throw.charge_time += dt;
+
+            // Predict the arc until it hits the terrain
+            let vel = throw.launch_velocity(origin);
+            let mut prev = origin;
+            let mut time = PREDICTION_STEP;
+            while time < PREDICTION_TIME {
+                let next = Point::new(
+                    origin.x + vel.x * time,
+                    origin.y + vel.y * time + 0.5 * grav * time * time,
+                );
+                if system_data
+                    .terrain
+                    .line_collides(prev.as_i32(), next.as_i32())
+                    .is_some()
+                {
+                    break;
+                }
+
+                if next.x >= 0.0 && next.y >= 0.0 {
+                    throw.arc.push(next.as_usize());
+                }
+
+                prev = next;
+                time += PREDICTION_STEP;
+            }
+        } else if throw.charging {
+            // The mouse button is released, throw the spear
+            let spear = system_data.entities.create();
+            system_data.updater.insert(spear, Projectile);
+            system_data.updater.insert(spear, WorldPosition(origin));
+            system_data
+                .updater
+                .insert(spear, throw.launch_velocity(origin));
+            system_data.updater.insert(spear, Arrow(7.0));
+            system_data.updater.insert(spear, Line::new(SPEAR_COLOR));
+            system_data
+                .updater
+                .insert(spear, Trail::new(4, 0.05, SPEAR_TRAIL_COLOR));
+            system_data.updater.insert(spear, Damage(20.0));
+            system_data.updater.insert(spear, DamageType::Pierce);
+            system_data.updater.insert(
+                spear,
+                ProjectileBoundingBox(BoundingBox::new(Point::new(0.0, 0.0), Point::new(1.0, 1.0))),
+            );
+            system_data.updater.insert(spear, IgnoreCollision::Ally);
+
+            throw.charging = false;
+            throw.charge_time = 0.0;
+            throw.cooldown_left = THROW_COOLDOWN;
+            system_data.stats.projectiles_fired += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+        world.insert(DeltaTime::new(0.1));
+        world.insert(Gravity(98.1));
+        world.insert(Terrain::new((400, 400)));
+        world.insert(Statistics::default());
+        world.insert(SpearThrow::default());
+
+        world
+    }
+
+    fn run(world: &mut World, right_is_down: bool) {
+        world
+            .write_resource::<SpearThrow>()
+            .handle_mouse((300, 315), right_is_down);
+        SpearThrowSystem.run_now(world);
+        world.maintain();
+    }
+
+    #[test]
+    fn charge_is_clamped_to_the_full_strength() {
+        let origin = Point::new(THROW_ORIGIN.0, THROW_ORIGIN.1);
+        let mut throw = SpearThrow::default();
+        throw.handle_mouse((300, 315), true);
+        assert!((throw.launch_velocity(origin).x - MIN_THROW_SPEED).abs() < 1e-9);
+
+        throw.charge_time = FULL_CHARGE_TIME * 3.0;
+        assert_eq!(throw.charge_fraction(), 1.0);
+        assert!((throw.launch_velocity(origin).x - MAX_THROW_SPEED).abs() < 1e-9);
+    }
+
+    #[test]
+    fn spear_is_thrown_on_release_and_cools_down() {
+        let mut world = world();
+        run(&mut world, true);
+        run(&mut world, true);
+        assert!(!world.read_resource::<SpearThrow>().arc.is_empty());
+
+        run(&mut world, false);
+        assert_eq!(world.read_resource::<Statistics>().projectiles_fired, 1);
+        assert_eq!(world.read_storage::<Projectile>().join().count(), 1);
+
+        // Holding the button during the cooldown doesn't charge a new spear
+        run(&mut world, true);
+        {
+            let throw = world.read_resource::<SpearThrow>();
+            assert_eq!(throw.charge_time, 0.0);
+            assert!(throw.arc.is_empty());
+        }
+
+        // Releasing it during the cooldown doesn't throw one either
+        run(&mut world, false);
+        assert_eq!(world.read_resource::<Statistics>().projectiles_fired, 1);
+        assert_eq!(world.read_storage::<Projectile>().join().count(), 1);
+
+        // Once cooled down a new spear can be charged
+        world.write_resource::<SpearThrow>().cooldown_left = 0.0;
+        run(&mut world, true);
+        assert!(world.read_resource::<SpearThrow>().charge_time > 0.0);
+    }
+
+    #[test]
+    fn spear_is_thrown_from_above_the_castle_wall() {
+        let mut terrain = Terrain::new((400, 400));
+        assert_eq!(throw_origin(&terrain).y, THROW_ORIGIN.1);
+
+        // A castle wall covering the window
+        for y in 300..400 {
+            for x in 0..40 {
+                terrain.draw_pixel((x, y), 0xFF_80_80_80);
+            }
+        }
+        let origin = throw_origin(&terrain);
+        assert_eq!((origin.x, origin.y), (THROW_ORIGIN.0, 299.0));
+        assert!(terrain
+            .line_collides(origin.as_i32(), (origin.x as i32 + 5, origin.y as i32))
+            .is_none());
+    }
+}
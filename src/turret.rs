@@ -1,10 +1,25 @@
 use cgmath::MetricSpace;
-use rand::distributions::{Distribution, Uniform};
+use rand::{
+    distributions::{Distribution, Uniform},
+    Rng,
+};
 use specs::prelude::*;
 use specs_derive::Component;
 
 use super::*;
 
+// The maximum fraction the speed of a projectile in a volley differs from the aimed shot
+const VOLLEY_SPEED_JITTER: f64 = 0.05;
+
+/// How the projectiles of a volley are spread over the cone.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VolleyPattern {
+    /// The angles are evenly distributed with the outer ones on the edges of the cone.
+    Even,
+    /// Every angle is picked at random inside the cone.
+    Random,
+}
+
 #[derive(Component, Debug)]
 pub struct Turret {
     pub delay: f64,
@@ -12,6 +27,13 @@ pub struct Turret {
     pub max_strength: f64,
     pub flight_time: f64,
     pub strength_variation: f64,
+    /// Only shoot at units that can be seen without terrain in between.
+    pub line_of_sight: bool,
+    /// Amount of projectiles fired at once.
+    pub volley_count: usize,
+    /// The angle in radians of the cone the projectiles of a volley are spread over.
+    pub volley_spread: f64,
+    pub volley_pattern: VolleyPattern,
 
     pub delay_left: f64,
 }
@@ -24,6 +46,10 @@ impl Default for Turret {
             max_strength: 210.0,
             flight_time: 3.0,
             strength_variation: 0.1,
+            line_of_sight: false,
+            volley_count: 1,
+            volley_spread: 0.0,
+            volley_pattern: VolleyPattern::Even,
 
             delay_left: 0.0,
         }
@@ -33,6 +59,73 @@ impl Default for Turret {
 #[derive(Component, Debug, Copy, Clone)]
 pub struct TurretOffset(pub (f64, f64));
 
+/// Calculate the velocity a projectile needs to travel the distance in the flight time.
+///
+/// The drag is the rate per second at which the velocity decays, use 0.0 for no drag.
+pub fn launch_velocity_for(
+    target_delta: (f64, f64),
+    flight_time: f64,
+    gravity: f64,
+    drag: f64,
+) -> Velocity {
+    if drag <= 0.0 {
+        return Velocity::new(
+            target_delta.0 / flight_time,
+            (target_delta.1 - 0.5 * gravity * flight_time * flight_time) / flight_time,
+        );
+    }
+
+    // With drag the velocity exponentially approaches the terminal velocity of gravity / drag
+    let spread = (1.0 - (-drag * flight_time).exp()) / drag;
+    let terminal = gravity / drag;
+    Velocity::new(
+        target_delta.0 / spread,
+        (target_delta.1 - terminal * flight_time) / spread + terminal,
+    )
+}
+
+/// Split an aimed shot into the velocities of all the projectiles in a volley.
+///
+/// The angles are spread around the direction of the base velocity and every speed gets a small
+/// random variation, a single projectile keeps the base velocity.
+pub fn volley_velocities<R: Rng>(
+    base: Velocity,
+    count: usize,
+    spread: f64,
+    pattern: VolleyPattern,
+    rng: &mut R,
+) -> Vec<Velocity> {
+    if count <= 1 {
+        return vec![base];
+    }
+
+    let speed = base.length();
+    let angle = base.y.atan2(base.x);
+    let jitter = Uniform::new_inclusive(1.0 - VOLLEY_SPEED_JITTER, 1.0 + VOLLEY_SPEED_JITTER);
+    let half_spread = spread / 2.0;
+
+    (0..count)
+        .map(|i| {
+            let offset = match pattern {
+                VolleyPattern::Even => -half_spread + spread * i as f64 / (count - 1) as f64,
+                VolleyPattern::Random => {
+                    if half_spread > 0.0 {
+                        rng.gen_range(-half_spread..half_spread)
+                    } else {
+                        0.0
+                    }
+                }
+            };
+            let speed = speed * jitter.sample(rng);
+
+            Velocity::new(
+                (angle + offset).cos() * speed,
+                (angle + offset).sin() * speed,
+            )
+        })
+        .collect()
+}
+
 #[derive(SystemData)]
 pub struct TurretUnitSystemData<'a> {
     turret: ReadStorage<'a, Turret>,
@@ -59,6 +152,11 @@ impl<'a> System<'a> for TurretUnitSystem {
             pos.0.x = wpos.0.x + (offset.0).0;
             pos.0.y = wpos.0.y + (offset.0).1;
 
+            // Fleeing units don't stop to shoot
+            if *state == UnitState::Flee {
+                continue;
+            }
+
             let unit_stop_moving_offset = turret.delay / 4.0;
             if turret.delay_left > unit_stop_moving_offset {
                 // Set the state if the unit turret just shot
@@ -76,21 +174,21 @@ pub struct TurretSystemData<'a> {
     entities: Entities<'a>,
     dt: Read<'a, DeltaTime>,
     grav: Read<'a, Gravity>,
+    terrain: Read<'a, Terrain>,
     ally: ReadStorage<'a, Ally>,
     enemy: ReadStorage<'a, Enemy>,
     pos: ReadStorage<'a, Point>,
     wpos: ReadStorage<'a, WorldPosition>,
     sprite: ReadStorage<'a, ProjectileSprite>,
-    arrow: ReadStorage<'a, Arrow>,
-    line: ReadStorage<'a, Line>,
-    mask: ReadStorage<'a, MaskId>,
-    ignore: ReadStorage<'a, IgnoreCollision>,
+    split: ReadStorage<'a, Split>,
+    projectile: ProjectileComponents<'a>,
+    zones: Read<'a, ProtectionZones>,
     bb: ReadStorage<'a, ProjectileBoundingBox>,
     ubb: ReadStorage<'a, BoundingBox>,
-    dmg: ReadStorage<'a, Damage>,
     walk: ReadStorage<'a, Walk>,
     state: ReadStorage<'a, UnitState>,
     turret: WriteStorage<'a, Turret>,
+    stats: Write<'a, Statistics>,
     updater: Read<'a, LazyUpdate>,
 }
 
@@ -102,15 +200,21 @@ impl<'a> System<'a> for TurretSystem {
         let dt = system_data.dt.to_seconds();
         let grav = system_data.grav.0;
 
-        for (e, tpos, bb, dmg, turret) in (
+        // Only turrets with a damage fire projectiles
+        for (e, tpos, bb, _, turret) in (
             &*system_data.entities,
             &system_data.pos,
             &system_data.bb,
-            &system_data.dmg,
+            &system_data.projectile.dmg,
             &mut system_data.turret,
         )
             .join()
         {
+            // Fleeing units don't shoot
+            if system_data.state.get(e) == Some(&UnitState::Flee) {
+                continue;
+            }
+
             turret.delay_left -= dt;
             if turret.delay_left > 0.0 {
                 continue;
@@ -135,6 +239,15 @@ impl<'a> System<'a> for TurretSystem {
                     pos.x += ubb.width() / 2.0;
                     pos.y += ubb.height() / 2.0;
 
+                    // Ignore units hidden behind the terrain
+                    if turret.line_of_sight
+                        && !system_data
+                            .terrain
+                            .line_of_sight(tpos.as_i32(), pos.as_i32())
+                    {
+                        continue;
+                    }
+
                     if *state == UnitState::Walk {
                         pos.x += walk.speed * turret.flight_time;
                     }
@@ -159,6 +272,15 @@ impl<'a> System<'a> for TurretSystem {
                     pos.x += ubb.width() / 2.0;
                     pos.y += ubb.height() / 2.0;
 
+                    // Ignore units hidden behind the terrain
+                    if turret.line_of_sight
+                        && !system_data
+                            .terrain
+                            .line_of_sight(tpos.as_i32(), pos.as_i32())
+                    {
+                        continue;
+                    }
+
                     if *state == UnitState::Walk {
                         pos.x += walk.speed * turret.flight_time;
                     }
@@ -183,45 +305,240 @@ impl<'a> System<'a> for TurretSystem {
                 1.0
             };
 
-            let time = turret.flight_time;
-            let vx = (closest.x - tpos.x + variation) / time;
-            let vy = (closest.y + 0.5 * -grav * time * time - tpos.y) / time;
-
-            if (vx * vx + vy * vy).sqrt() < turret.max_strength {
-                // Shoot the turret
-                let projectile = system_data.entities.create();
-                system_data.updater.insert(projectile, Projectile);
-                system_data
-                    .updater
-                    .insert(projectile, WorldPosition(Point::new(tpos.x, tpos.y)));
-                system_data
-                    .updater
-                    .insert(projectile, Velocity::new(vx, vy));
-                system_data.updater.insert(projectile, *bb);
-                system_data.updater.insert(projectile, *dmg);
-                let entity: Option<&MaskId> = system_data.mask.get(e);
-                if let Some(mask_e) = entity {
-                    system_data.updater.insert(projectile, *mask_e);
-                }
-                let entity: Option<&ProjectileSprite> = system_data.sprite.get(e);
-                if let Some(sprite_e) = entity {
-                    system_data.updater.insert(projectile, sprite_e.0);
-                }
-                let entity: Option<&Arrow> = system_data.arrow.get(e);
-                if let Some(arrow_e) = entity {
-                    system_data.updater.insert(projectile, *arrow_e);
-                }
-                let entity: Option<&Line> = system_data.line.get(e);
-                if let Some(line_e) = entity {
-                    system_data.updater.insert(projectile, *line_e);
-                }
-                let entity: Option<&IgnoreCollision> = system_data.ignore.get(e);
-                if let Some(ignore_e) = entity {
-                    system_data.updater.insert(projectile, *ignore_e);
+            let gravity_scale: Option<&GravityScale> = system_data.projectile.gravity_scale.get(e);
+            let drag: Option<&Drag> = system_data.projectile.drag.get(e);
+            let vel = launch_velocity_for(
+                (closest.x - tpos.x + variation, closest.y - tpos.y),
+                turret.flight_time,
+                grav * gravity_scale.map_or(1.0, |s| s.0),
+                drag.map_or(0.0, |d| d.0),
+            );
+
+            let max_speed: Option<&MaxSpeed> = system_data.projectile.max_speed.get(e);
+            let max_strength =
+                max_speed.map_or(turret.max_strength, |m| m.0.min(turret.max_strength));
+
+            // Don't shoot when the target can't be reached
+            if vel.length() < max_strength {
+                // Shoot the turret, a volley fires all its projectiles at the same time
+                let velocities = volley_velocities(
+                    vel,
+                    turret.volley_count,
+                    turret.volley_spread,
+                    turret.volley_pattern,
+                    &mut rand::thread_rng(),
+                );
+                for vel in velocities.iter() {
+                    let projectile = system_data.entities.create();
+                    system_data.updater.insert(projectile, Projectile);
+                    system_data
+                        .updater
+                        .insert(projectile, WorldPosition(Point::new(tpos.x, tpos.y)));
+                    system_data.updater.insert(projectile, *vel);
+                    system_data.updater.insert(projectile, Owner(e));
+                    system_data.updater.insert(projectile, *bb);
+                    // Shooting from inside the protection zone of the other side is discouraged
+                    let damage_factor = if system_data
+                        .zones
+                        .opposing(is_ally.is_some())
+                        .contains(tpos.x)
+                    {
+                        PROTECTED_PROJECTILE_DAMAGE
+                    } else {
+                        1.0
+                    };
+                    copy_projectile_components(
+                        e,
+                        projectile,
+                        damage_factor,
+                        &system_data.projectile,
+                        &system_data.updater,
+                    );
+                    let entity: Option<&ProjectileSprite> = system_data.sprite.get(e);
+                    if let Some(sprite_e) = entity {
+                        system_data.updater.insert(projectile, sprite_e.0);
+                    }
+                    let entity: Option<&Split> = system_data.split.get(e);
+                    if let Some(split_e) = entity {
+                        system_data.updater.insert(projectile, *split_e);
+                    }
                 }
 
                 turret.delay_left = turret.delay;
+                system_data.stats.projectiles_fired += velocities.len();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Integrate the flight the same way as the projectile system does
+    fn land_after(vel: Velocity, flight_time: f64, gravity: f64, drag: f64) -> (f64, f64) {
+        let steps = 100_000;
+        let dt = flight_time / steps as f64;
+        let (mut x, mut y, mut vx, mut vy) = (0.0, 0.0, vel.x, vel.y);
+        for _ in 0..steps {
+            x += vx * dt;
+            y += vy * dt;
+            vy += gravity * dt;
+
+            let factor = (-drag * dt).exp();
+            vx *= factor;
+            vy *= factor;
+        }
+
+        (x, y)
+    }
+
+    #[test]
+    fn hits_target_without_drag() {
+        let vel = launch_velocity_for((200.0, -30.0), 2.0, 98.1, 0.0);
+        let (x, y) = land_after(vel, 2.0, 98.1, 0.0);
+
+        assert!((x - 200.0).abs() < 0.1);
+        assert!((y + 30.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn hits_target_with_drag() {
+        let vel = launch_velocity_for((-300.0, 50.0), 3.0, 98.1, 0.05);
+        let (x, y) = land_after(vel, 3.0, 98.1, 0.05);
+
+        assert!((x + 300.0).abs() < 0.1);
+        assert!((y - 50.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn farther_targets_need_faster_shots() {
+        let near = launch_velocity_for((100.0, 0.0), 2.0, 98.1, 0.0);
+        let far = launch_velocity_for((400.0, 0.0), 2.0, 98.1, 0.0);
+
+        assert!(far.length() > near.length());
+    }
+
+    fn angle(vel: &Velocity) -> f64 {
+        vel.y.atan2(vel.x)
+    }
+
+    #[test]
+    fn single_shot_keeps_the_aimed_velocity() {
+        let base = Velocity::new(30.0, -40.0);
+        let velocities =
+            volley_velocities(base, 1, 0.5, VolleyPattern::Even, &mut rand::thread_rng());
+
+        assert_eq!(velocities.len(), 1);
+        assert_eq!((velocities[0].x, velocities[0].y), (base.x, base.y));
+    }
+
+    #[test]
+    fn even_volley_spreads_the_angles_over_the_cone() {
+        let base = Velocity::new(30.0, -40.0);
+        let velocities =
+            volley_velocities(base, 5, 0.4, VolleyPattern::Even, &mut rand::thread_rng());
+
+        assert_eq!(velocities.len(), 5);
+        for (i, vel) in velocities.iter().enumerate() {
+            let expected = angle(&base) - 0.2 + 0.1 * i as f64;
+            assert!((angle(vel) - expected).abs() < 1e-9);
+            assert!((vel.length() - 50.0).abs() <= 50.0 * VOLLEY_SPEED_JITTER + 1e-9);
+        }
+    }
+
+    #[test]
+    fn random_volley_stays_inside_the_cone() {
+        let base = Velocity::new(-50.0, 0.0);
+        let velocities = volley_velocities(
+            base,
+            50,
+            0.4,
+            VolleyPattern::Random,
+            &mut rand::thread_rng(),
+        );
+
+        assert_eq!(velocities.len(), 50);
+        for vel in velocities.iter() {
+            // Measured from the left so the angles don't wrap around
+            let offset = (-vel.y).atan2(-vel.x);
+            assert!(offset.abs() <= 0.2);
+        }
+        assert!(velocities
+            .iter()
+            .any(|vel| (angle(vel) - angle(&velocities[0])).abs() > 1e-6));
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        crate::register_components(&mut world);
+        world.insert(DeltaTime::new(0.1));
+        world.insert(Gravity(98.1));
+        world.insert(ProtectionZones::default());
+        world.insert(Statistics::default());
+
+        // A castle on the left with the turret inside of it
+        let mut terrain = Terrain::new((200, 100));
+        for y in 40..100 {
+            for x in 0..20 {
+                terrain.draw_pixel((x, y), 0xFF_80_80_80);
+            }
+        }
+        world.insert(terrain);
+
+        world
+            .create_entity()
+            .with(Ally)
+            .with(Turret {
+                delay: 1.0,
+                max_strength: 300.0,
+                flight_time: 1.0,
+                line_of_sight: true,
+                ..Turret::default()
+            })
+            .with(Point::new(10.0, 45.0))
+            .with(ProjectileBoundingBox(BoundingBox::new(
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 1.0),
+            )))
+            .with(Damage(1.0))
+            .build();
+        world
+            .create_entity()
+            .with(Enemy)
+            .with(WorldPosition(Point::new(100.0, 40.0)))
+            .with(Walk::new(
+                BoundingBox::new(Point::new(0.0, 0.0), Point::new(2.0, 2.0)),
+                0.0,
+            ))
+            .with(BoundingBox::new(Point::new(0.0, 0.0), Point::new(2.0, 2.0)))
+            .with(UnitState::Walk)
+            .build();
+
+        world
+    }
+
+    #[test]
+    fn turret_inside_its_castle_sees_out() {
+        let mut world = world();
+        TurretSystem.run_now(&world);
+        world.maintain();
+
+        assert_eq!(world.read_resource::<Statistics>().projectiles_fired, 1);
+    }
+
+    #[test]
+    fn terrain_wall_blocks_the_shot() {
+        let mut world = world();
+        {
+            let mut terrain = world.write_resource::<Terrain>();
+            for y in 0..100 {
+                terrain.draw_pixel((50, y), 0xFF_80_60_40);
+            }
+        }
+        TurretSystem.run_now(&world);
+        world.maintain();
+
+        assert_eq!(world.read_resource::<Statistics>().projectiles_fired, 0);
+    }
+}
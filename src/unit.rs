@@ -1,10 +1,52 @@
 use cgmath::Point2;
 use collision::Discrete;
+use rand::distributions::{Distribution, Uniform};
 use specs::prelude::*;
 use specs_derive::Component;
 
 use super::*;
 
+const BLOOD_COLOR: u32 = 0xAC_32_33;
+const CORPSE_COLOR: u32 = 0x76_24_25;
+
+const FOOTPRINT_COLOR: u32 = 0xFF_4B_2E_22;
+// Seconds before a footprint has faded away
+const FOOTPRINT_LIFETIME: f64 = 6.0;
+const FOOTPRINT_DECAL: [((i32, i32), u32); 1] = [((0, 0), FOOTPRINT_COLOR)];
+
+// Seconds it takes for the lost health on a health bar to disappear
+const HEALTH_CATCH_UP_TIME: f64 = 0.6;
+
+// Melee hits dealing at least this much damage stagger the unit that is hit
+const STAGGER_DAMAGE: f64 = 8.0;
+// Seconds a staggered unit can't walk or attack
+const STAGGER_TIME: f64 = 0.4;
+// Seconds after a stagger is over during which the unit can't be staggered again
+const STAGGER_IMMUNITY_TIME: f64 = 1.0;
+const KNOCKBACK_SPEED_PER_DAMAGE: f64 = 4.0;
+const MAX_KNOCKBACK_SPEED: f64 = 60.0;
+// Amount of pixels per second the knockback speed decreases
+const KNOCKBACK_DECELERATION: f64 = 150.0;
+// Highest step in pixels a unit can be pushed onto, anything higher stops the knockback
+const MAX_KNOCKBACK_STEP: i32 = 2;
+// Highest step in pixels a unit walks onto, anything higher has to be climbed
+const MAX_WALK_STEP: i32 = 2;
+
+// Amount of blood particles spawned when a unit dies
+const DEATH_BLOOD_PARTICLES: usize = 8;
+
+// Stain left on the ground where a unit died
+const CORPSE_DECAL: [((i32, i32), u32); 8] = [
+    ((-2, 0), CORPSE_COLOR),
+    ((-1, 0), CORPSE_COLOR),
+    ((0, 0), CORPSE_COLOR),
+    ((1, 0), CORPSE_COLOR),
+    ((2, 0), CORPSE_COLOR),
+    ((-1, 1), CORPSE_COLOR),
+    ((0, 1), CORPSE_COLOR),
+    ((1, 1), CORPSE_COLOR),
+];
+
 #[derive(Component, Debug, Eq, PartialEq)]
 pub enum UnitState {
     // The path is clear and the unit can walk
@@ -18,11 +60,26 @@ pub enum UnitState {
     Melee,
     // The unit is shooting at an enemy unit
     Shoot,
+    // The unit lost its morale and runs back to its own castle
+    Flee,
 }
 
 #[derive(Component, Debug, Copy, Clone)]
 pub struct Health(pub f64);
 
+/// Multipliers of the damage the unit takes for each type, missing types deal the full damage.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Resistance(pub &'static [(DamageType, f64)]);
+
+impl Resistance {
+    pub fn multiplier(&self, damage_type: DamageType) -> f64 {
+        self.0
+            .iter()
+            .find(|(resisted, _)| *resisted == damage_type)
+            .map_or(1.0, |(_, multiplier)| *multiplier)
+    }
+}
+
 #[derive(Component, Debug, Copy, Clone)]
 pub struct HealthBar {
     pub health: f64,
@@ -30,6 +87,27 @@ pub struct HealthBar {
     pub width: usize,
     pub pos: Point2<usize>,
     pub offset: (i32, i32),
+    /// Health shown as recently lost damage, catches up with the actual health.
+    pub delayed_health: f64,
+
+    catch_up: Tween,
+    catch_up_elapsed: f64,
+}
+
+impl HealthBar {
+    pub fn new(health: f64, width: usize, offset: (i32, i32)) -> Self {
+        HealthBar {
+            health,
+            max_health: health,
+            width,
+            pos: Point2::new(0, 0),
+            offset,
+            delayed_health: health,
+
+            catch_up: Tween::new(health, health, 0.0, Easing::Linear),
+            catch_up_elapsed: 0.0,
+        }
+    }
 }
 
 #[derive(Component, Debug, Copy, Clone)]
@@ -44,12 +122,77 @@ impl Walk {
     }
 }
 
+/// Pushes the unit back and stops it from walking and attacking after a heavy hit.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Stagger {
+    /// Multiplier of the knockback, units with lower values are pushed back less.
+    pub knockback: f64,
+
+    // Horizontal speed the unit is pushed back with
+    speed: f64,
+}
+
+impl Stagger {
+    pub fn new(knockback: f64) -> Self {
+        Stagger {
+            knockback,
+
+            speed: 0.0,
+        }
+    }
+
+    /// Stagger the unit when the damage is high enough and it's not immune.
+    ///
+    /// The direction is 1.0 to push the unit to the right and -1.0 to the left.
+    pub fn hit(&mut self, effects: &mut StatusEffects, dmg: f64, direction: f64) -> bool {
+        if dmg < STAGGER_DAMAGE
+            || effects.is_staggered()
+            || effects.is_active(StatusEffectKind::StaggerImmunity)
+        {
+            return false;
+        }
+
+        effects.apply(StatusEffect::timed(StatusEffectKind::Stagger, STAGGER_TIME));
+        effects.apply(StatusEffect::timed(
+            StatusEffectKind::StaggerImmunity,
+            STAGGER_TIME + STAGGER_IMMUNITY_TIME,
+        ));
+        self.speed = direction
+            * (dmg * KNOCKBACK_SPEED_PER_DAMAGE).min(MAX_KNOCKBACK_SPEED)
+            * self.knockback;
+
+        true
+    }
+}
+
+/// Leaves fading footprints on the terrain while the unit walks.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Footprints {
+    /// Amount of pixels walked between two footprints.
+    pub interval: f64,
+
+    distance_left: f64,
+    last_x: Option<f64>,
+}
+
+impl Footprints {
+    pub fn new(interval: f64) -> Self {
+        Footprints {
+            interval,
+
+            distance_left: interval,
+            last_x: None,
+        }
+    }
+}
+
 #[derive(SystemData)]
 pub struct WalkSystemData<'a> {
     dt: Read<'a, DeltaTime>,
     terrain: Read<'a, Terrain>,
     dest: ReadStorage<'a, Destination>,
     walk: ReadStorage<'a, Walk>,
+    effects: ReadStorage<'a, StatusEffects>,
     state: WriteStorage<'a, UnitState>,
     pos: WriteStorage<'a, WorldPosition>,
 }
@@ -60,30 +203,96 @@ impl<'a> System<'a> for WalkSystem {
 
     fn run(&mut self, mut system_data: Self::SystemData) {
         let dt = system_data.dt.to_seconds();
+        let level_width = system_data.terrain.size().0 as f64;
 
-        for (dest, walk, state, pos) in (
+        for (dest, walk, effects, state, pos) in (
             &system_data.dest,
             &system_data.walk,
+            system_data.effects.maybe(),
             &mut system_data.state,
             &mut system_data.pos,
         )
             .join()
         {
-            // Don't walk when the unitstate is not saying that it can walk
-            if *state != UnitState::Walk {
+            let speed = effects.map_or(walk.speed, |effects| effects.walk_speed(walk.speed));
+            let direction = (dest.0 - pos.0.x).signum();
+
+            let fleeing = *state == UnitState::Flee;
+            let (speed, direction) = if fleeing {
+                // Run back until the unit is in front of its own castle
+                if in_friendly_territory(pos.0.x, dest.0, level_width) {
+                    continue;
+                }
+                (speed * FLEE_SPEED, -direction)
+            } else if *state == UnitState::Walk {
+                (speed, direction)
+            } else {
+                // Don't walk when the unitstate is not saying that it can walk
+                continue;
+            };
+            if matches!(effects, Some(effects) if effects.is_staggered()) {
                 continue;
             }
 
-            let hit_box = walk.bounds + *pos.0;
-            if let Some(hit) = system_data.terrain.rect_collides(hit_box) {
-                if hit.1 == hit_box.min.y as i32 {
-                    // Top edge of bounding box is hit, try to climb
+            if blocks_walking(&system_data.terrain, walk.bounds + *pos.0) {
+                // Top edge of bounding box is hit, try to climb, a fleeing unit keeps fleeing and
+                // waits until the ledge is gone
+                if !fleeing {
                     *state = UnitState::Climb;
-                    continue;
                 }
+                continue;
             }
 
-            pos.0.x += walk.speed * dt * (dest.0 - pos.0.x).signum();
+            pos.0.x += speed * dt * direction;
+        }
+    }
+}
+
+// Whether there is terrain in the hit box above the height of a step, overhangs that are
+// completely above the unit can be walked under
+fn blocks_walking(terrain: &Terrain, hit_box: BoundingBox) -> bool {
+    let (x, y, width, height) = hit_box.to_i32();
+    let step_top = y + height - MAX_WALK_STEP;
+
+    (x.max(0)..x + width).any(|x| {
+        terrain
+            .surfaces_at(x as usize)
+            .any(|(top, bottom)| (top as i32) < step_top && bottom as i32 >= y)
+    })
+}
+
+pub struct FootprintSystem;
+impl<'a> System<'a> for FootprintSystem {
+    type SystemData = (
+        Write<'a, Terrain>,
+        ReadStorage<'a, WorldPosition>,
+        ReadStorage<'a, Walk>,
+        WriteStorage<'a, Footprints>,
+    );
+
+    fn run(&mut self, (mut terrain, pos, walk, mut footprints): Self::SystemData) {
+        for (pos, walk, footprints) in (&pos, &walk, &mut footprints).join() {
+            let last_x = footprints.last_x.replace(pos.0.x).unwrap_or(pos.0.x);
+            footprints.distance_left -= (pos.0.x - last_x).abs();
+            if footprints.distance_left > 0.0 {
+                continue;
+            }
+            footprints.distance_left = footprints.interval;
+
+            let feet = Point::new(
+                pos.0.x + (walk.bounds.min.x + walk.bounds.max.x) / 2.0,
+                pos.0.y + walk.bounds.max.y,
+            );
+            // Only leave a footprint when the unit is standing on the ground
+            if let Some(surface) = terrain.surface_below(feet) {
+                if (surface.y - feet.y).abs() <= 1.0 {
+                    terrain.stamp_fading_decal(
+                        surface.as_i32(),
+                        &FOOTPRINT_DECAL,
+                        FOOTPRINT_LIFETIME,
+                    );
+                }
+            }
         }
     }
 }
@@ -91,13 +300,32 @@ impl<'a> System<'a> for WalkSystem {
 pub struct HealthBarSystem;
 impl<'a> System<'a> for HealthBarSystem {
     type SystemData = (
+        Read<'a, DeltaTime>,
         ReadStorage<'a, Health>,
         ReadStorage<'a, WorldPosition>,
         WriteStorage<'a, HealthBar>,
     );
 
-    fn run(&mut self, (health, pos, mut health_bar): Self::SystemData) {
+    fn run(&mut self, (dt, health, pos, mut health_bar): Self::SystemData) {
+        let dt = dt.to_seconds();
+
         for (health, pos, health_bar) in (&health, &pos, &mut health_bar).join() {
+            if health.0 < health_bar.health {
+                // Start shrinking the lost health from where it's currently shown
+                health_bar.catch_up = Tween::new(
+                    health_bar.delayed_health,
+                    health.0,
+                    HEALTH_CATCH_UP_TIME,
+                    Easing::CubicIn,
+                );
+                health_bar.catch_up_elapsed = 0.0;
+            }
+            health_bar.catch_up_elapsed += dt;
+            health_bar.delayed_health = health_bar
+                .catch_up
+                .value_at(health_bar.catch_up_elapsed)
+                .max(health.0);
+
             health_bar.health = health.0;
             health_bar.pos = pos.0.as_usize();
             health_bar.pos.x = (health_bar.pos.x as i32 + health_bar.offset.0) as usize;
@@ -106,6 +334,48 @@ impl<'a> System<'a> for HealthBarSystem {
     }
 }
 
+pub struct StaggerSystem;
+impl<'a> System<'a> for StaggerSystem {
+    type SystemData = (
+        Read<'a, DeltaTime>,
+        Read<'a, Terrain>,
+        ReadStorage<'a, Walk>,
+        ReadStorage<'a, StatusEffects>,
+        WriteStorage<'a, Stagger>,
+        WriteStorage<'a, WorldPosition>,
+    );
+
+    fn run(&mut self, (dt, terrain, walk, effects, mut stagger, mut pos): Self::SystemData) {
+        let dt = dt.to_seconds();
+
+        for (walk, effects, stagger, pos) in (&walk, &effects, &mut stagger, &mut pos).join() {
+            if !effects.is_staggered() {
+                continue;
+            }
+
+            let mut next = pos.0;
+            next.x += stagger.speed * dt;
+
+            // Only push the unit horizontally, a slope that would lift it up stops the knockback
+            let hit_box = walk.bounds + *next;
+            if let Some(hit) = terrain.rect_collides(hit_box) {
+                if hit.1 < hit_box.max.y as i32 - MAX_KNOCKBACK_STEP {
+                    stagger.speed = 0.0;
+                    continue;
+                }
+            }
+            pos.0 = next;
+
+            let deceleration = KNOCKBACK_DECELERATION * dt;
+            stagger.speed = if stagger.speed.abs() > deceleration {
+                stagger.speed - deceleration * stagger.speed.signum()
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
 pub struct UnitFallSystem;
 impl<'a> System<'a> for UnitFallSystem {
     type SystemData = (
@@ -123,8 +393,14 @@ impl<'a> System<'a> for UnitFallSystem {
 
             // Move the units if they collide with the ground in a loop until they don't touch the ground anymore
             loop {
+                // Only the bottom row is checked, so a unit under an overhang stays on the ground
+                // it's standing on instead of being pushed through the overhang
                 let hit_box = walk.bounds + *pos.0;
-                match terrain.rect_collides(hit_box) {
+                let feet = BoundingBox::new(
+                    Point::new(hit_box.min.x, hit_box.max.y - 1.0),
+                    Point::new(hit_box.max.x, hit_box.max.y),
+                );
+                match terrain.rect_collides(feet) {
                     Some(_) => {
                         pos.0.y -= 1.0;
                     }
@@ -239,6 +515,11 @@ impl<'a> System<'a> for UnitCollideSystem {
                     continue;
                 }
 
+                // Fleeing units don't block or fight other units
+                if system_data.state.get(e2) == Some(&UnitState::Flee) {
+                    continue;
+                }
+
                 // Join a melee
                 let is_melee = if let Some(state) = system_data.state.get_mut(e2) {
                     *state == UnitState::Melee
@@ -291,13 +572,38 @@ impl<'a> System<'a> for UnitCollideSystem {
     }
 }
 
+/// The damage after the resistance of the unit is applied.
+///
+/// Damage without a type and units without resistances deal and take the full damage.
+pub fn resisted_damage(
+    resistance: Option<&Resistance>,
+    damage_type: Option<DamageType>,
+    dmg: f64,
+) -> f64 {
+    match (resistance, damage_type) {
+        (Some(resistance), Some(damage_type)) => dmg * resistance.multiplier(damage_type),
+        _ => dmg,
+    }
+}
+
+/// Damage the unit and count it in the statistics, returns true when this damage killed it.
+///
+/// Dead units are only removed when the world is maintained, a unit that already died this frame
+/// can't die again.
 pub fn reduce_unit_health<'a>(
     entities: &'a Entities,
+    stats: &mut Statistics,
     unit: Entity,
+    is_ally: bool,
     health: &'a mut Health,
     dmg: f64,
 ) -> bool {
+    if health.0 <= 0.0 {
+        return false;
+    }
+
     health.0 -= dmg;
+    stats.register_damage(is_ally, dmg, health.0);
     if health.0 <= 0.0 {
         let _ = entities.delete(unit);
 
@@ -306,3 +612,210 @@ pub fn reduce_unit_health<'a>(
         false
     }
 }
+
+/// Whether the unit died this frame but wasn't removed yet.
+pub fn is_dead(health: Option<&Health>) -> bool {
+    matches!(health, Some(health) if health.0 <= 0.0)
+}
+
+/// Heal the unit without going over its maximum health.
+pub fn restore_unit_health(health: &mut Health, max_health: Option<f64>, heal: f64) {
+    health.0 += heal;
+    if let Some(max_health) = max_health {
+        health.0 = health.0.min(max_health);
+    }
+}
+
+/// Show that a unit died with a floating cross, a burst of blood and a stain on the ground.
+pub fn spawn_death_effects(entities: &Entities, updater: &LazyUpdate, pos: WorldPosition) {
+    updater.insert(
+        entities.create(),
+        FloatingText::new("x".to_string(), pos.0, 2.0),
+    );
+
+    // The blood particles stick to the terrain where they land
+    let between_x = Uniform::new(-30.0, 30.0);
+    let between_y = Uniform::new(-50.0, -10.0);
+    let mut rng = rand::thread_rng();
+    for _ in 0..DEATH_BLOOD_PARTICLES {
+        let blood = entities.create();
+        updater.insert(blood, PixelParticle::new(BLOOD_COLOR, 10.0));
+        updater.insert(blood, pos);
+        updater.insert(
+            blood,
+            Velocity::new(between_x.sample(&mut rng), between_y.sample(&mut rng)),
+        );
+    }
+
+    // The stain is part of the terrain so it will be removed together with it by craters
+    updater.exec_mut(move |world| {
+        let mut terrain = world.write_resource::<Terrain>();
+        if let Some(surface) = terrain.surface_below(pos.0) {
+            terrain.stamp_decal(surface.as_i32(), &CORPSE_DECAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRT: u32 = 0xFF_80_60_40;
+
+    // A terrain with the ground starting at row 15 and the pixels in the rectangles solid
+    fn terrain(rects: &[(usize, usize, usize, usize)]) -> Terrain {
+        let mut terrain = Terrain::new((20, 20));
+        for (x1, y1, x2, y2) in rects.iter().chain([(0, 15, 20, 20)].iter()) {
+            for y in *y1..*y2 {
+                for x in *x1..*x2 {
+                    terrain.buffer[x + y * 20] = DIRT;
+                }
+            }
+        }
+
+        terrain
+    }
+
+    fn hit_box() -> BoundingBox {
+        BoundingBox::new(Point::new(5.0, 5.0), Point::new(9.0, 15.0))
+    }
+
+    #[test]
+    fn unit_killed_twice_in_a_frame_dies_once() {
+        let mut world = World::new();
+        world.register::<Health>();
+        let unit = world.create_entity().with(Health(10.0)).build();
+
+        let mut stats = Statistics::default();
+        let mut health = Health(10.0);
+        let entities = world.entities();
+        assert!(reduce_unit_health(
+            &entities,
+            &mut stats,
+            unit,
+            false,
+            &mut health,
+            15.0
+        ));
+        // The unit is still joined by the other systems until the world is maintained
+        assert!(is_dead(Some(&health)));
+        assert!(!reduce_unit_health(
+            &entities,
+            &mut stats,
+            unit,
+            false,
+            &mut health,
+            15.0
+        ));
+
+        assert_eq!(stats.enemies_killed, 1);
+        assert_eq!(stats.damage_dealt, 10.0);
+        assert_eq!(health.0, -5.0);
+    }
+
+    #[test]
+    fn standing_on_the_ground_doesnt_block() {
+        assert!(!blocks_walking(&terrain(&[]), hit_box()));
+    }
+
+    #[test]
+    fn wall_has_to_be_climbed() {
+        assert!(blocks_walking(&terrain(&[(8, 0, 10, 15)]), hit_box()));
+    }
+
+    #[test]
+    fn overhang_above_the_unit_can_be_walked_under() {
+        assert!(!blocks_walking(&terrain(&[(7, 3, 12, 5)]), hit_box()));
+        // The roof of a tunnel with a wall behind it
+        assert!(!blocks_walking(
+            &terrain(&[(7, 0, 12, 5), (12, 0, 14, 15)]),
+            hit_box()
+        ));
+    }
+
+    #[test]
+    fn overhang_at_the_head_blocks() {
+        assert!(blocks_walking(&terrain(&[(7, 3, 12, 6)]), hit_box()));
+    }
+
+    #[test]
+    fn undercut_wall_blocks() {
+        assert!(blocks_walking(&terrain(&[(8, 0, 10, 14)]), hit_box()));
+        assert!(blocks_walking(&terrain(&[(8, 0, 10, 13)]), hit_box()));
+    }
+
+    #[test]
+    fn low_step_doesnt_block() {
+        assert!(!blocks_walking(&terrain(&[(8, 13, 10, 15)]), hit_box()));
+        assert!(blocks_walking(&terrain(&[(8, 12, 10, 15)]), hit_box()));
+    }
+
+    #[test]
+    fn only_heavy_hits_stagger() {
+        let mut effects = StatusEffects::default();
+        let mut stagger = Stagger::new(0.5);
+
+        assert!(!stagger.hit(&mut effects, STAGGER_DAMAGE - 0.1, 1.0));
+        assert!(!effects.is_staggered());
+
+        assert!(stagger.hit(&mut effects, STAGGER_DAMAGE, -1.0));
+        assert!(effects.is_staggered());
+        assert_eq!(
+            stagger.speed,
+            -STAGGER_DAMAGE * KNOCKBACK_SPEED_PER_DAMAGE * 0.5
+        );
+
+        // The knockback speed is capped
+        let mut stagger = Stagger::new(1.0);
+        assert!(stagger.hit(&mut StatusEffects::default(), 1000.0, 1.0));
+        assert_eq!(stagger.speed, MAX_KNOCKBACK_SPEED);
+    }
+
+    #[test]
+    fn staggered_units_are_immune_for_a_while() {
+        let mut effects = StatusEffects::default();
+        let mut stagger = Stagger::new(1.0);
+        assert!(stagger.hit(&mut effects, 10.0, 1.0));
+
+        // Not while staggered
+        assert!(!stagger.hit(&mut effects, 10.0, 1.0));
+
+        // Not right after the stagger is over
+        effects.update(STAGGER_TIME + 0.01);
+        assert!(!effects.is_staggered());
+        assert!(!stagger.hit(&mut effects, 10.0, 1.0));
+
+        // Only when the immunity has worn off as well
+        effects.update(STAGGER_IMMUNITY_TIME);
+        assert!(stagger.hit(&mut effects, 10.0, 1.0));
+    }
+
+    const RESISTANCE: [(DamageType, f64); 2] =
+        [(DamageType::Pierce, 0.5), (DamageType::Crush, 1.2)];
+
+    #[test]
+    fn resistance_multiplies_the_damage_of_its_types() {
+        let resistance = Resistance(&RESISTANCE);
+        assert_eq!(resistance.multiplier(DamageType::Pierce), 0.5);
+        assert_eq!(resistance.multiplier(DamageType::Crush), 1.2);
+        // Types that aren't listed deal the full damage
+        assert_eq!(resistance.multiplier(DamageType::Blast), 1.0);
+
+        assert_eq!(
+            resisted_damage(Some(&resistance), Some(DamageType::Pierce), 10.0),
+            5.0
+        );
+        assert_eq!(
+            resisted_damage(Some(&resistance), Some(DamageType::Crush), 10.0),
+            12.0
+        );
+    }
+
+    #[test]
+    fn full_damage_without_resistance_or_type() {
+        let resistance = Resistance(&RESISTANCE);
+        assert_eq!(resisted_damage(Some(&resistance), None, 10.0), 10.0);
+        assert_eq!(resisted_damage(None, Some(DamageType::Pierce), 10.0), 10.0);
+        assert_eq!(Resistance(&[]).multiplier(DamageType::Pierce), 1.0);
+    }
+}
@@ -0,0 +1,243 @@
+use specs::prelude::*;
+use specs_derive::Component;
+
+use super::*;
+
+pub const CHEVRON_COLOR: u32 = 0xFF_FB_F2_36;
+
+/// The stats of a unit that are improved when it levels up.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UnitStats {
+    pub max_health: f64,
+    pub melee_damage: f64,
+    /// Damage of the projectiles the unit shoots, zero when it doesn't shoot.
+    pub projectile_damage: f64,
+    pub walk_speed: f64,
+}
+
+/// How many kills a level takes and how much every level improves the stats.
+#[derive(Debug, Copy, Clone)]
+pub struct VeterancySettings {
+    /// The total amount of kills needed for every level.
+    pub kill_thresholds: [usize; 3],
+    /// Fraction of the base max health added every level.
+    pub health_bonus: f64,
+    /// Fraction of the base damage added every level.
+    pub damage_bonus: f64,
+    /// Fraction of the base walking speed added every level.
+    pub speed_bonus: f64,
+}
+
+impl Default for VeterancySettings {
+    fn default() -> Self {
+        VeterancySettings {
+            kill_thresholds: [2, 5, 10],
+            health_bonus: 0.15,
+            damage_bonus: 0.15,
+            speed_bonus: 0.05,
+        }
+    }
+}
+
+/// The kills of a unit, it levels up after enough of them.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Veterancy {
+    pub settings: VeterancySettings,
+    /// The stats of the unit without any levels, the modifiers are always applied to these.
+    pub base: UnitStats,
+    pub kills: usize,
+
+    // The level the stats of the unit have been updated for
+    applied_level: usize,
+}
+
+impl Veterancy {
+    pub fn new(base: UnitStats) -> Self {
+        Veterancy {
+            settings: VeterancySettings::default(),
+            base,
+            kills: 0,
+
+            applied_level: 0,
+        }
+    }
+
+    pub fn level(&self) -> usize {
+        self.settings
+            .kill_thresholds
+            .iter()
+            .filter(|threshold| self.kills >= **threshold)
+            .count()
+    }
+
+    /// Count a kill, returns true when the unit reached a new level.
+    pub fn register_kill(&mut self) -> bool {
+        let level = self.level();
+        self.kills += 1;
+
+        self.level() > level
+    }
+
+    /// The stats with the bonuses of the current level applied.
+    pub fn apply_modifiers(&self, base: &UnitStats) -> UnitStats {
+        let level = self.level() as f64;
+        let damage = 1.0 + self.settings.damage_bonus * level;
+
+        UnitStats {
+            max_health: base.max_health * (1.0 + self.settings.health_bonus * level),
+            melee_damage: base.melee_damage * damage,
+            projectile_damage: base.projectile_damage * damage,
+            walk_speed: base.walk_speed * (1.0 + self.settings.speed_bonus * level),
+        }
+    }
+}
+
+/// Count the kill for the unit that made it, when it's a unit that can level up.
+pub fn credit_kill(veterancy: &mut WriteStorage<Veterancy>, killer: Option<Entity>) {
+    if let Some(veterancy) = killer.and_then(|killer| veterancy.get_mut(killer)) {
+        veterancy.register_kill();
+    }
+}
+
+#[derive(SystemData)]
+pub struct VeterancySystemData<'a> {
+    entities: Entities<'a>,
+    veterancy: WriteStorage<'a, Veterancy>,
+    health: WriteStorage<'a, Health>,
+    health_bar: WriteStorage<'a, HealthBar>,
+    melee: WriteStorage<'a, Melee>,
+    dmg: WriteStorage<'a, Damage>,
+    walk: WriteStorage<'a, Walk>,
+}
+
+pub struct VeterancySystem;
+impl<'a> System<'a> for VeterancySystem {
+    type SystemData = VeterancySystemData<'a>;
+
+    fn run(&mut self, mut system_data: Self::SystemData) {
+        for (e, veterancy, health) in (
+            &*system_data.entities,
+            &mut system_data.veterancy,
+            &mut system_data.health,
+        )
+            .join()
+        {
+            let level = veterancy.level();
+            if level == veterancy.applied_level {
+                continue;
+            }
+            veterancy.applied_level = level;
+
+            let stats = veterancy.apply_modifiers(&veterancy.base);
+
+            // The gained max health is healed
+            if let Some(health_bar) = system_data.health_bar.get_mut(e) {
+                health.0 += stats.max_health - health_bar.max_health;
+                health_bar.max_health = stats.max_health;
+            }
+            if let Some(melee) = system_data.melee.get_mut(e) {
+                melee.set_damage(stats.melee_damage);
+            }
+            if let Some(dmg) = system_data.dmg.get_mut(e) {
+                dmg.0 = stats.projectile_damage;
+            }
+            if let Some(walk) = system_data.walk.get_mut(e) {
+                walk.speed = stats.walk_speed;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> UnitStats {
+        UnitStats {
+            max_health: 20.0,
+            melee_damage: 5.0,
+            projectile_damage: 4.0,
+            walk_speed: 20.0,
+        }
+    }
+
+    fn veterancy_with_kills(kills: usize) -> Veterancy {
+        let mut veterancy = Veterancy::new(base());
+        for _ in 0..kills {
+            veterancy.register_kill();
+        }
+
+        veterancy
+    }
+
+    #[test]
+    fn kills_are_credited_to_the_owner_of_the_projectile() {
+        let mut world = World::new();
+        world.register::<Veterancy>();
+        world.register::<Owner>();
+
+        let archer = world.create_entity().with(Veterancy::new(base())).build();
+        let arrow = world.create_entity().with(Owner(archer)).build();
+        // Projectiles fired by something that can't level up, like a castle turret
+        let boulder = world.create_entity().build();
+
+        let killers: Vec<Option<Entity>> = [arrow, arrow, boulder]
+            .iter()
+            .map(|projectile| {
+                world
+                    .read_storage::<Owner>()
+                    .get(*projectile)
+                    .map(|owner| owner.0)
+            })
+            .collect();
+        for killer in killers.iter() {
+            credit_kill(&mut world.write_storage::<Veterancy>(), *killer);
+        }
+
+        let veterancy = world.read_storage::<Veterancy>();
+        assert_eq!(veterancy.get(archer).unwrap().kills, 2);
+        assert_eq!(veterancy.get(archer).unwrap().level(), 1);
+    }
+
+    #[test]
+    fn levels_up_at_the_thresholds() {
+        let mut veterancy = Veterancy::new(base());
+
+        assert!(!veterancy.register_kill());
+        assert!(veterancy.register_kill());
+        assert_eq!(veterancy.level(), 1);
+
+        let veterancy = veterancy_with_kills(5);
+        assert_eq!(veterancy.level(), 2);
+
+        // There is no level after the last threshold
+        let veterancy = veterancy_with_kills(100);
+        assert_eq!(veterancy.level(), 3);
+    }
+
+    #[test]
+    fn levels_apply_the_multipliers() {
+        let stats = veterancy_with_kills(0).apply_modifiers(&base());
+        assert_eq!(stats, base());
+
+        // Two levels with the default bonuses
+        let stats = veterancy_with_kills(5).apply_modifiers(&base());
+        assert!((stats.max_health - 26.0).abs() < 1e-9);
+        assert!((stats.melee_damage - 6.5).abs() < 1e-9);
+        assert!((stats.projectile_damage - 5.2).abs() < 1e-9);
+        assert!((stats.walk_speed - 22.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn modifiers_dont_drift_when_the_base_changes() {
+        let mut veterancy = veterancy_with_kills(2);
+        let first = veterancy.apply_modifiers(&veterancy.base);
+        assert_eq!(veterancy.apply_modifiers(&veterancy.base), first);
+
+        // Reloading the constants mid-game applies the bonus to the new base only once
+        veterancy.base.max_health = 40.0;
+        let stats = veterancy.apply_modifiers(&veterancy.base);
+        assert!((stats.max_health - 46.0).abs() < 1e-9);
+        assert_eq!(veterancy.base.max_health, 40.0);
+    }
+}